@@ -0,0 +1,31 @@
+CREATE TABLE IF NOT EXISTS decks
+(
+    id          SERIAL PRIMARY KEY  NOT NULL,
+    name        VARCHAR(250)        NOT NULL,
+    path        VARCHAR(1000)       NOT NULL
+);
+
+CREATE UNIQUE INDEX index_decks_on_path 
+ON decks(path);
+
+INSERT INTO 
+decks(name, path)
+VALUES
+('Main', 'pictures');
+
+ALTER TABLE cards
+ADD COLUMN deck_id INTEGER NOT NULL DEFAULT 1,
+ADD CONSTRAINT fk_deck
+    FOREIGN KEY(deck_id) 
+    REFERENCES decks(id);
+ALTER TABLE cards
+ALTER COLUMN deck_id DROP DEFAULT;
+
+CREATE INDEX index_cards_on_deck_id
+ON cards(deck_id);
+
+ALTER TABLE chats_states
+ADD COLUMN deck_id INTEGER NOT NULL DEFAULT 1,
+ADD CONSTRAINT fk_deck
+    FOREIGN KEY(deck_id) 
+    REFERENCES decks(id);
@@ -0,0 +1,36 @@
+ALTER TABLE chats_states
+ALTER COLUMN language DROP DEFAULT;
+ALTER TABLE chats_states
+ALTER COLUMN language TYPE VARCHAR(35) USING language::text;
+ALTER TABLE chats_states
+ALTER COLUMN language SET DEFAULT 'en';
+DROP TYPE language;
+
+CREATE TABLE IF NOT EXISTS card_translations
+(
+    id                  SERIAL PRIMARY KEY          NOT NULL,
+    card_id             INTEGER                     NOT NULL,
+    language            VARCHAR(35)                 NOT NULL,
+    name                VARCHAR(1000)               NOT NULL,
+    description         TEXT                        NOT NULL,
+    telegram_file_id    VARCHAR(1000),
+    CONSTRAINT fk_card
+      FOREIGN KEY(card_id) 
+	    REFERENCES cards(id)
+);
+
+CREATE UNIQUE INDEX index_card_translations_on_card_id_and_language 
+ON card_translations(card_id, language);
+
+INSERT INTO card_translations (card_id, language, name, description, telegram_file_id)
+SELECT id, 'en', name_en, description_en, telegram_file_id_en FROM cards;
+INSERT INTO card_translations (card_id, language, name, description, telegram_file_id)
+SELECT id, 'ru', name_ru, description_ru, telegram_file_id_ru FROM cards;
+
+ALTER TABLE cards
+DROP COLUMN name_en,
+DROP COLUMN description_en,
+DROP COLUMN name_ru,
+DROP COLUMN description_ru,
+DROP COLUMN telegram_file_id_en,
+DROP COLUMN telegram_file_id_ru;
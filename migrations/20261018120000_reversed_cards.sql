@@ -0,0 +1,7 @@
+ALTER TABLE chats_states
+ADD COLUMN reversed_cards BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE card_translations
+ADD COLUMN reversed_name VARCHAR(1000),
+ADD COLUMN reversed_description TEXT,
+ADD COLUMN reversed_telegram_file_id VARCHAR(1000);
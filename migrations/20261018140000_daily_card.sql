@@ -0,0 +1,4 @@
+ALTER TABLE chats_states
+ADD COLUMN daily_card_time TIME,
+ADD COLUMN daily_card_utc_offset INTEGER NOT NULL DEFAULT 0,
+ADD COLUMN daily_card_sent_on DATE;
@@ -0,0 +1,2 @@
+UPDATE decks SET path = '.' WHERE path = 'pictures';
+UPDATE decks SET path = substring(path from 10) WHERE path LIKE 'pictures/%';
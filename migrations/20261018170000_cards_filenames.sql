@@ -0,0 +1,3 @@
+-- cards are imported by their file names, which are unique in a deck
+CREATE UNIQUE INDEX index_cards_on_deck_id_and_filename
+ON cards(deck_id, filename);
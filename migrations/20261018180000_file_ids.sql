@@ -0,0 +1,24 @@
+-- telegram file ids are valid only for the bot which has sent the file,
+-- content_hash is of the image file the id was got for
+CREATE TABLE IF NOT EXISTS file_ids
+(
+    bot_id          INTEGER             NOT NULL,
+    card_id         INTEGER             NOT NULL,
+    language        VARCHAR(35)         NOT NULL,
+    reversed        BOOLEAN             NOT NULL,
+    file_id         VARCHAR(1000)       NOT NULL,
+    content_hash    VARCHAR(64)         NOT NULL,
+    PRIMARY KEY (bot_id, card_id, language, reversed),
+    CONSTRAINT fk_bot
+      FOREIGN KEY(bot_id)
+	    REFERENCES bots(id),
+    CONSTRAINT fk_card
+      FOREIGN KEY(card_id)
+	    REFERENCES cards(id)
+);
+
+-- the ids which are there may be of another language's image and are not kept,
+-- the images are uploaded again
+ALTER TABLE card_translations
+DROP COLUMN telegram_file_id,
+DROP COLUMN reversed_telegram_file_id;
@@ -0,0 +1,18 @@
+-- every bot has its own subscribers, so the same chat can talk to several bots,
+-- there is one subscriber for a chat or a member of a chat per bot
+ALTER TABLE subscribers
+ADD COLUMN bot_id INTEGER REFERENCES bots(id);
+
+UPDATE subscribers SET bot_id = (
+    SELECT MIN(bot_id) FROM bots_subscribers WHERE bots_subscribers.subscriber_id = subscribers.id
+);
+-- the subscribers not linked to any bot are of the first one
+UPDATE subscribers SET bot_id = (SELECT MIN(id) FROM bots) WHERE bot_id IS NULL;
+INSERT INTO bots_subscribers (bot_id, subscriber_id)
+SELECT bot_id, id FROM subscribers
+WHERE NOT EXISTS (SELECT 1 FROM bots_subscribers WHERE bots_subscribers.subscriber_id = subscribers.id);
+ALTER TABLE subscribers ALTER COLUMN bot_id SET NOT NULL;
+
+DROP INDEX index_subscribers_on_chat_id_and_user_id;
+CREATE UNIQUE INDEX index_subscribers_on_bot_id_and_chat_id_and_user_id
+ON subscribers(bot_id, chat_id, COALESCE(user_id, 0));
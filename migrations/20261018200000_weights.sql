@@ -0,0 +1,7 @@
+-- cards with bigger weights are drawn more often
+ALTER TABLE cards
+ADD COLUMN weight INTEGER NOT NULL DEFAULT 1 CHECK (weight > 0);
+
+-- decks drawn with replacement don't put the drawn cards aside
+ALTER TABLE decks
+ADD COLUMN with_replacement BOOLEAN NOT NULL DEFAULT FALSE;
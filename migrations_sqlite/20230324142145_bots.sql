@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS bots
+(
+    id          INTEGER PRIMARY KEY AUTOINCREMENT   NOT NULL,
+    name        VARCHAR(250)                        NOT NULL,
+    active      BOOLEAN                             NOT NULL DEFAULT TRUE,
+    key         VARCHAR(250)                        NOT NULL
+);
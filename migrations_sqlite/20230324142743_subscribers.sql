@@ -0,0 +1,10 @@
+-- timestamps are stored as text in UTC
+CREATE TABLE IF NOT EXISTS subscribers
+(
+    id          INTEGER PRIMARY KEY AUTOINCREMENT   NOT NULL,
+    chat_id     VARCHAR(10000)                      NOT NULL,
+    created_at  TEXT                                NOT NULL
+);
+
+CREATE UNIQUE INDEX index_subscribers_chat_id 
+ON subscribers(chat_id);
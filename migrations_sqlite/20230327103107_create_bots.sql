@@ -0,0 +1,4 @@
+INSERT INTO 
+bots(name, active, key)
+VALUES
+('Main', true, 'bot_token');
\ No newline at end of file
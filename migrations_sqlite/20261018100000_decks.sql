@@ -0,0 +1,25 @@
+CREATE TABLE IF NOT EXISTS decks
+(
+    id          INTEGER PRIMARY KEY AUTOINCREMENT   NOT NULL,
+    name        VARCHAR(250)                        NOT NULL,
+    path        VARCHAR(1000)                       NOT NULL
+);
+
+CREATE UNIQUE INDEX index_decks_on_path 
+ON decks(path);
+
+INSERT INTO 
+decks(name, path)
+VALUES
+('Main', 'pictures');
+
+-- sqlite can't add a column with both a foreign key and a default,
+-- so deck_id columns are added without foreign keys
+ALTER TABLE cards
+ADD COLUMN deck_id INTEGER NOT NULL DEFAULT 1;
+
+CREATE INDEX index_cards_on_deck_id
+ON cards(deck_id);
+
+ALTER TABLE chats_states
+ADD COLUMN deck_id INTEGER NOT NULL DEFAULT 1;
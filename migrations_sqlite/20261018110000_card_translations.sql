@@ -0,0 +1,63 @@
+-- dropping the language check needs the table to be recreated
+CREATE TABLE chats_states_new
+(
+    id                    INTEGER PRIMARY KEY AUTOINCREMENT   NOT NULL,
+    bot_id                INTEGER                             NOT NULL,
+    subscriber_id         INTEGER                             NOT NULL,
+    drawn_cards           TEXT                                NOT NULL DEFAULT '[]',
+    language              VARCHAR(35)                         NOT NULL DEFAULT 'en',
+    descriptions_format   INTEGER                             NOT NULL DEFAULT 0,
+    deck_id               INTEGER                             NOT NULL DEFAULT 1,
+    CONSTRAINT fk_bot
+      FOREIGN KEY(bot_id) 
+	    REFERENCES bots(id),
+    CONSTRAINT fk_subscriber
+      FOREIGN KEY(subscriber_id) 
+	    REFERENCES subscribers(id),
+    CONSTRAINT fk_deck
+      FOREIGN KEY(deck_id) 
+	    REFERENCES decks(id)
+);
+
+INSERT INTO chats_states_new
+(id, bot_id, subscriber_id, drawn_cards, language, descriptions_format, deck_id)
+SELECT id, bot_id, subscriber_id, drawn_cards, language, descriptions_format, deck_id
+FROM chats_states;
+
+DROP TABLE chats_states;
+ALTER TABLE chats_states_new RENAME TO chats_states;
+
+CREATE UNIQUE INDEX index_chats_states_on_bot_id_and_subscriber_id 
+ON chats_states(bot_id, subscriber_id);
+CREATE UNIQUE INDEX index_chats_states_on_subscriber_id 
+ON chats_states(subscriber_id);
+CREATE INDEX index_chats_states_on_bot_id
+ON chats_states(bot_id);
+
+CREATE TABLE IF NOT EXISTS card_translations
+(
+    id                  INTEGER PRIMARY KEY AUTOINCREMENT   NOT NULL,
+    card_id             INTEGER                             NOT NULL,
+    language            VARCHAR(35)                         NOT NULL,
+    name                VARCHAR(1000)                       NOT NULL,
+    description         TEXT                                NOT NULL,
+    telegram_file_id    VARCHAR(1000),
+    CONSTRAINT fk_card
+      FOREIGN KEY(card_id) 
+	    REFERENCES cards(id)
+);
+
+CREATE UNIQUE INDEX index_card_translations_on_card_id_and_language 
+ON card_translations(card_id, language);
+
+INSERT INTO card_translations (card_id, language, name, description, telegram_file_id)
+SELECT id, 'en', name_en, description_en, telegram_file_id_en FROM cards;
+INSERT INTO card_translations (card_id, language, name, description, telegram_file_id)
+SELECT id, 'ru', name_ru, description_ru, telegram_file_id_ru FROM cards;
+
+ALTER TABLE cards DROP COLUMN name_en;
+ALTER TABLE cards DROP COLUMN description_en;
+ALTER TABLE cards DROP COLUMN name_ru;
+ALTER TABLE cards DROP COLUMN description_ru;
+ALTER TABLE cards DROP COLUMN telegram_file_id_en;
+ALTER TABLE cards DROP COLUMN telegram_file_id_ru;
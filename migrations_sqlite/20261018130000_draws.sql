@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS draws
+(
+    id                  INTEGER PRIMARY KEY AUTOINCREMENT   NOT NULL,
+    subscriber_id       INTEGER                             NOT NULL,
+    card_id             INTEGER                             NOT NULL,
+    deck_id             INTEGER                             NOT NULL,
+    reversed            BOOLEAN                             NOT NULL,
+    spread              VARCHAR(250),
+    spread_position     INTEGER,
+    drawn_at            TEXT                                NOT NULL,
+    CONSTRAINT fk_subscriber
+      FOREIGN KEY(subscriber_id) 
+	    REFERENCES subscribers(id),
+    CONSTRAINT fk_card
+      FOREIGN KEY(card_id) 
+	    REFERENCES cards(id),
+    CONSTRAINT fk_deck
+      FOREIGN KEY(deck_id) 
+	    REFERENCES decks(id)
+);
+
+CREATE INDEX index_draws_on_subscriber_id_and_drawn_at
+ON draws(subscriber_id, drawn_at);
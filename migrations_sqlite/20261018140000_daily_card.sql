@@ -0,0 +1,3 @@
+ALTER TABLE chats_states ADD COLUMN daily_card_time TEXT;
+ALTER TABLE chats_states ADD COLUMN daily_card_utc_offset INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE chats_states ADD COLUMN daily_card_sent_on TEXT;
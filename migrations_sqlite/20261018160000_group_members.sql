@@ -0,0 +1,11 @@
+-- in group chats with per-member decks every member has its own state,
+-- such subscribers have the user id, the chat itself has none
+ALTER TABLE subscribers
+ADD COLUMN user_id BIGINT;
+
+DROP INDEX index_subscribers_chat_id;
+CREATE UNIQUE INDEX index_subscribers_on_chat_id_and_user_id
+ON subscribers(chat_id, COALESCE(user_id, 0));
+
+ALTER TABLE chats_states
+ADD COLUMN per_member_decks BOOLEAN NOT NULL DEFAULT FALSE;
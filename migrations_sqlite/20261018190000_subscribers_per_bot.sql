@@ -0,0 +1,18 @@
+-- every bot has its own subscribers, so the same chat can talk to several bots,
+-- there is one subscriber for a chat or a member of a chat per bot,
+-- sqlite adds a NOT NULL column only with a default, every subscriber gets its bot below
+ALTER TABLE subscribers
+ADD COLUMN bot_id INTEGER NOT NULL DEFAULT 0;
+
+UPDATE subscribers SET bot_id = COALESCE((
+    SELECT MIN(bot_id) FROM bots_subscribers WHERE bots_subscribers.subscriber_id = subscribers.id
+), 0);
+-- the subscribers not linked to any bot are of the first one
+UPDATE subscribers SET bot_id = (SELECT MIN(id) FROM bots) WHERE bot_id = 0;
+INSERT INTO bots_subscribers (bot_id, subscriber_id)
+SELECT bot_id, id FROM subscribers
+WHERE NOT EXISTS (SELECT 1 FROM bots_subscribers WHERE bots_subscribers.subscriber_id = subscribers.id);
+
+DROP INDEX index_subscribers_on_chat_id_and_user_id;
+CREATE UNIQUE INDEX index_subscribers_on_bot_id_and_chat_id_and_user_id
+ON subscribers(bot_id, chat_id, COALESCE(user_id, 0));
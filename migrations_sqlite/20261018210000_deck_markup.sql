@@ -0,0 +1,3 @@
+-- the markup format of the card descriptions, plain or markdown
+ALTER TABLE decks
+ADD COLUMN markup TEXT NOT NULL DEFAULT 'plain';
@@ -0,0 +1,219 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use teloxide::{prelude::*, RequestError};
+use time::{OffsetDateTime, Time};
+
+use crate::config::Config;
+use crate::reloadable::Reloadable;
+use crate::telegram_interactions::AdminCommand;
+use crate::storage::Storage;
+use crate::{file_ids, process, spreads, translations, warmup};
+
+//telegram allows about 30 messages per second to different chats
+const BROADCAST_INTERVAL: Duration = Duration::from_millis(50);
+//the progress message is updated after this many chats
+const BROADCAST_PROGRESS_STEP: usize = 100;
+//the failure report lists at most this many chats
+const MAX_REPORTED_FAILURES: usize = 20;
+
+pub struct Stats {
+    pub subscribers: i64,
+    pub new_subscribers: i64,
+    pub daily_card_subscribers: i64,
+    pub draws_today: i64,
+    //language code -> number of chats
+    pub languages: Vec<(String, i64)>,
+}
+
+pub struct ChatState {
+    pub subscriber_id: i32,
+    pub created_at: OffsetDateTime,
+    pub language: String,
+    pub descriptions_format: i32,
+    pub reversed_cards: bool,
+    pub deck_name: String,
+    pub drawn_cards: i32,
+    pub daily_card_time: Option<Time>,
+    pub daily_card_utc_offset: i32,
+    pub draws: i64,
+}
+
+pub fn is_admin(chat_id: ChatId, config: &Config) -> bool {
+    config.admin_chat_ids.contains(&chat_id.0)
+}
+
+//admin commands are answered in plain text, errors included,
+//since they are for the operators only
+pub async fn process_admin_command(
+    command: AdminCommand,
+    chat_id: ChatId,
+    bot: Bot,
+    storage: &dyn Storage,
+    translation: &Reloadable<translations::Translation>,
+    spreads: &Reloadable<spreads::Spreads>,
+    config: &Config,
+) -> Result<()> {
+    let result = match command {
+        AdminCommand::Stats => stats(storage).await,
+        AdminCommand::Reload => reload(storage, translation, spreads, config).await,
+        AdminCommand::Broadcast(text) => broadcast(text.trim(), chat_id, &bot, storage).await,
+        AdminCommand::Whois(argument) => whois(argument.trim(), storage).await,
+        AdminCommand::Warmup => warmup(chat_id, &bot, storage, translation, config).await,
+    };
+    let text = match result {
+        Ok(text) => text,
+        Err(e) => {
+            tracing::warn!("Error in admin command: {e:?}");
+            format!("Error: {e}")
+        }
+    };
+    bot.send_message(chat_id, text).await?;
+    Ok(())
+}
+
+async fn stats(storage: &dyn Storage) -> Result<String> {
+    let stats = storage.get_stats().await?;
+    let mut text = format!(
+        "Subscribers: {} (new today: {})\nCard of the day: {}\nDraws today: {}\nLanguages:",
+        stats.subscribers, stats.new_subscribers, stats.daily_card_subscribers, stats.draws_today
+    );
+    for (language, count) in stats.languages {
+        text.push_str(&format!("\n{}: {}", language, count));
+    }
+    Ok(text)
+}
+
+//translations and spreads are replaced only if all of them are loaded,
+//new decks and cards are added to the database, file ids of changed images are forgotten
+async fn reload(
+    storage: &dyn Storage,
+    translation: &Reloadable<translations::Translation>,
+    spreads: &Reloadable<spreads::Spreads>,
+    config: &Config,
+) -> Result<String> {
+    let new_translation = translations::translation(&config.pictures_path, &config.default_language)?;
+    let new_spreads = spreads::spreads(&config.pictures_path)?;
+    storage.check_cards_table(&config.pictures_path).await?;
+    let forgotten = file_ids::check_all_file_ids(&config.pictures_path, storage).await?;
+    let mut text = format!(
+        "Reloaded: {} languages, {} spreads, {} decks, {} file ids of changed images forgotten.",
+        new_translation.languages().count(),
+        new_spreads.all().len(),
+        storage.get_decks().await?.len(),
+        forgotten
+    );
+    if !new_translation.problems().is_empty() {
+        text.push_str(&format!("\nTranslation problems:\n{}", new_translation.problems().join("\n")));
+    }
+    translation.set(new_translation);
+    spreads.set(new_spreads);
+    Ok(text)
+}
+
+async fn broadcast(text: &str, admin_chat_id: ChatId, bot: &Bot, storage: &dyn Storage) -> Result<String> {
+    if text.is_empty() {
+        return Ok("Usage: /broadcast <text>".to_string());
+    }
+    let chat_ids = storage.get_subscribers_chat_ids().await?;
+    let total = chat_ids.len();
+    let progress = bot
+        .send_message(admin_chat_id, format!("Broadcasting to {} chats...", total))
+        .await?;
+    let mut failures = vec![];
+    for (index, chat_id) in chat_ids.iter().enumerate() {
+        if let Err(e) = send_broadcast_message(text, chat_id, bot).await {
+            failures.push(format!("{}: {}", chat_id, e));
+        }
+        if (index + 1) % BROADCAST_PROGRESS_STEP == 0 {
+            let _ = bot
+                .edit_message_text(
+                    admin_chat_id,
+                    progress.id,
+                    format!("Broadcasting: {}/{} done, {} failed...", index + 1, total, failures.len()),
+                )
+                .await;
+        }
+        tokio::time::sleep(BROADCAST_INTERVAL).await;
+    }
+    let mut report = format!(
+        "Broadcast finished: {} of {} sent, {} failed.",
+        total - failures.len(),
+        total,
+        failures.len()
+    );
+    for failure in failures.iter().take(MAX_REPORTED_FAILURES) {
+        report.push('\n');
+        report.push_str(failure);
+    }
+    if failures.len() > MAX_REPORTED_FAILURES {
+        report.push_str(&format!("\n...and {} more", failures.len() - MAX_REPORTED_FAILURES));
+    }
+    Ok(report)
+}
+
+//if telegram asks to slow down, waits and tries once more
+async fn send_broadcast_message(text: &str, chat_id: &str, bot: &Bot) -> Result<()> {
+    let chat_id = ChatId(chat_id.parse()?);
+    match bot.send_message(chat_id, text).await {
+        Err(RequestError::RetryAfter(duration)) => {
+            tokio::time::sleep(duration).await;
+            bot.send_message(chat_id, text).await?;
+        }
+        result => {
+            result?;
+        }
+    }
+    Ok(())
+}
+
+//uploads the card images which have no file ids to the storage chat
+async fn warmup(
+    admin_chat_id: ChatId,
+    bot: &Bot,
+    storage: &dyn Storage,
+    translation: &Reloadable<translations::Translation>,
+    config: &Config,
+) -> Result<String> {
+    let storage_chat_id = warmup::storage_chat_id(config.storage_chat_id)?;
+    let languages: Vec<process::Language> = translation.get().languages().collect();
+    let (images, mut report) = warmup::pending_images(&languages, &config.pictures_path, storage).await?;
+    bot.send_message(admin_chat_id, format!("Uploading {} images to the storage chat...", images.len()))
+        .await?;
+    warmup::upload(images, storage_chat_id, bot, &config.pictures_path, storage, &mut report).await?;
+    Ok(report.to_string())
+}
+
+async fn whois(chat_id: &str, storage: &dyn Storage) -> Result<String> {
+    if chat_id.is_empty() {
+        return Ok("Usage: /whois <chat_id>".to_string());
+    }
+    let chat_state = match storage.get_chat_state(chat_id.to_string()).await? {
+        Some(chat_state) => chat_state,
+        None => return Ok(format!("Chat {} is not found.", chat_id)),
+    };
+    let descriptions = match chat_state.descriptions_format {
+        process::FULL_DESCRIPTIONS => "full".to_string(),
+        process::NAMES_ONLY => "names only".to_string(),
+        process::NO_DESCRIPTIONS => "none".to_string(),
+        other => format!("unknown ({})", other),
+    };
+    let daily_card = match chat_state.daily_card_time {
+        Some(time) => process::format_daily_card(time, chat_state.daily_card_utc_offset),
+        None => "off".to_string(),
+    };
+    Ok(format!(
+        "Chat {}\nSubscriber: {}, since {}\nLanguage: {}\nDescriptions: {}\nDeck: {}\n\
+        Reversed cards: {}\nDrawn cards: {}\nDraws: {}\nCard of the day: {}",
+        chat_id,
+        chat_state.subscriber_id,
+        chat_state.created_at.date(),
+        chat_state.language,
+        descriptions,
+        chat_state.deck_name,
+        if chat_state.reversed_cards { "on" } else { "off" },
+        chat_state.drawn_cards,
+        chat_state.draws,
+        daily_card
+    ))
+}
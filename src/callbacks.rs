@@ -0,0 +1,138 @@
+use crate::telegram_interactions::Command;
+
+//telegram doesn't accept callback data longer than this
+pub const MAX_CALLBACK_DATA_LENGTH: usize = 64;
+
+//payload of inline keyboard buttons, independent of buttons' texts,
+//serialized as a short id optionally followed by ':' and an argument
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Callback {
+    About,
+    MainMenu,
+    Card,
+    Spreads,
+    Spread(String),
+    Shuffle,
+    Settings,
+    Languages,
+    Language(String),
+    Descriptions,
+    FullDescriptions,
+    NamesOnly,
+    NoDescriptions,
+    Decks,
+    Deck(i32),
+    Reversed,
+    ReversedOn,
+    ReversedOff,
+    History(i64),
+    HistoryEntry(i32),
+    DailyCard(String),
+    DeckPage(i64),
+    DeckCard(i32),
+    GroupDecks,
+    SharedDeck,
+    MemberDecks,
+}
+
+impl Callback {
+    pub fn data(&self) -> String {
+        match self {
+            Callback::About => "ab".to_string(),
+            Callback::MainMenu => "mm".to_string(),
+            Callback::Card => "cd".to_string(),
+            Callback::Spreads => "sp".to_string(),
+            Callback::Spread(id) => format!("sp:{}", id),
+            Callback::Shuffle => "sh".to_string(),
+            Callback::Settings => "st".to_string(),
+            Callback::Languages => "lg".to_string(),
+            Callback::Language(code) => format!("lg:{}", code),
+            Callback::Descriptions => "ds".to_string(),
+            Callback::FullDescriptions => "fd".to_string(),
+            Callback::NamesOnly => "no".to_string(),
+            Callback::NoDescriptions => "nd".to_string(),
+            Callback::Decks => "dk".to_string(),
+            Callback::Deck(id) => format!("dk:{}", id),
+            Callback::Reversed => "rv".to_string(),
+            Callback::ReversedOn => "rv:1".to_string(),
+            Callback::ReversedOff => "rv:0".to_string(),
+            Callback::History(page) => format!("hs:{}", page),
+            Callback::HistoryEntry(id) => format!("he:{}", id),
+            Callback::DailyCard(argument) if argument.is_empty() => "dc".to_string(),
+            Callback::DailyCard(argument) => format!("dc:{}", argument),
+            Callback::DeckPage(page) => format!("bw:{}", page),
+            Callback::DeckCard(id) => format!("bc:{}", id),
+            Callback::GroupDecks => "gd".to_string(),
+            Callback::SharedDeck => "gd:0".to_string(),
+            Callback::MemberDecks => "gd:1".to_string(),
+        }
+    }
+
+    pub fn parse(data: &str) -> Option<Callback> {
+        let (id, argument) = match data.split_once(':') {
+            Some((id, argument)) => (id, Some(argument)),
+            None => (data, None),
+        };
+        let callback = match (id, argument) {
+            ("ab", None) => Callback::About,
+            ("mm", None) => Callback::MainMenu,
+            ("cd", None) => Callback::Card,
+            ("sp", None) => Callback::Spreads,
+            ("sp", Some(id)) => Callback::Spread(id.to_string()),
+            ("sh", None) => Callback::Shuffle,
+            ("st", None) => Callback::Settings,
+            ("lg", None) => Callback::Languages,
+            ("lg", Some(code)) => Callback::Language(code.to_string()),
+            ("ds", None) => Callback::Descriptions,
+            ("fd", None) => Callback::FullDescriptions,
+            ("no", None) => Callback::NamesOnly,
+            ("nd", None) => Callback::NoDescriptions,
+            ("dk", None) => Callback::Decks,
+            ("dk", Some(id)) => Callback::Deck(id.parse().ok()?),
+            ("rv", None) => Callback::Reversed,
+            ("rv", Some("1")) => Callback::ReversedOn,
+            ("rv", Some("0")) => Callback::ReversedOff,
+            ("hs", Some(page)) => Callback::History(page.parse().ok()?),
+            ("he", Some(id)) => Callback::HistoryEntry(id.parse().ok()?),
+            ("bw", Some(page)) => Callback::DeckPage(page.parse().ok()?),
+            ("bc", Some(id)) => Callback::DeckCard(id.parse().ok()?),
+            ("gd", None) => Callback::GroupDecks,
+            ("gd", Some("0")) => Callback::SharedDeck,
+            ("gd", Some("1")) => Callback::MemberDecks,
+            ("dc", argument) => Callback::DailyCard(argument.unwrap_or_default().to_string()),
+            _ => return None,
+        };
+        Some(callback)
+    }
+
+    pub fn command(self) -> Command {
+        match self {
+            Callback::About => Command::About,
+            Callback::MainMenu => Command::MainMenu,
+            Callback::Card => Command::Card,
+            Callback::Spreads => Command::Spread(String::new()),
+            Callback::Spread(id) => Command::Spread(id),
+            Callback::Shuffle => Command::Shuffle,
+            Callback::Settings => Command::Settings,
+            Callback::Languages => Command::Language(String::new()),
+            Callback::Language(code) => Command::Language(code),
+            Callback::Descriptions => Command::Description,
+            Callback::FullDescriptions => Command::FullDescription,
+            Callback::NamesOnly => Command::NamesOnly,
+            Callback::NoDescriptions => Command::NoDescription,
+            Callback::Decks => Command::Decks(String::new()),
+            Callback::Deck(id) => Command::Decks(id.to_string()),
+            Callback::Reversed => Command::Reversed,
+            Callback::ReversedOn => Command::ReversedOn,
+            Callback::ReversedOff => Command::ReversedOff,
+            Callback::History(page) => Command::History(page.to_string()),
+            Callback::HistoryEntry(id) => Command::HistoryEntry(id.to_string()),
+            Callback::DailyCard(argument) => Command::DailyCard(argument),
+            Callback::DeckPage(page) => Command::Deck(page.to_string()),
+            Callback::DeckCard(id) => Command::DeckCard(id.to_string()),
+            Callback::GroupDecks => Command::GroupDecks,
+            Callback::SharedDeck => Command::SharedDeck,
+            Callback::MemberDecks => Command::MemberDecks,
+        }
+    }
+}
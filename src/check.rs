@@ -0,0 +1,133 @@
+use std::{collections::BTreeSet, fmt, path::Path};
+
+use anyhow::{anyhow, Result};
+use image::ImageFormat;
+
+use crate::config::StartupCheck;
+use crate::file_ids;
+use crate::storage::Storage;
+use crate::translations::{self, Translation};
+
+//the limits of telegram for photos
+const MAX_PHOTO_SIZE: u64 = 10 * 1024 * 1024;
+const MAX_PHOTO_DIMENSIONS_SUM: u32 = 10000;
+const MAX_PHOTO_RATIO: u32 = 20;
+//formats telegram shows as photos and the image features can decode
+const PHOTO_FORMATS: [ImageFormat; 2] = [ImageFormat::Jpeg, ImageFormat::Png];
+
+//what is wrong with translations and deck images, found before anyone runs into it
+#[derive(Default)]
+pub struct CheckReport {
+    //language: key, the keys the bot uses which the default language doesn't have
+    pub missing_keys: Vec<String>,
+    //other languages which don't match the default one
+    pub translation_problems: Vec<String>,
+    //paths relative to pictures_path
+    pub missing_images: Vec<String>,
+    pub invalid_images: Vec<String>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing_keys.is_empty()
+            && self.translation_problems.is_empty()
+            && self.missing_images.is_empty()
+            && self.invalid_images.is_empty()
+    }
+
+    //on start the problems are logged or stop the bot
+    pub fn apply(&self, startup_check: StartupCheck) -> Result<()> {
+        if self.is_ok() {
+            return Ok(());
+        }
+        match startup_check {
+            StartupCheck::Warn => {
+                tracing::warn!("{}", self);
+                Ok(())
+            }
+            StartupCheck::Refuse => Err(anyhow!("Not starting:\n{}", self)),
+        }
+    }
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "Translations and images are fine.");
+        }
+        writeln!(f, "missing translations {}: {}", self.missing_keys.len(), self.missing_keys.join(", "))?;
+        writeln!(
+            f,
+            "mismatched translations {}: {}",
+            self.translation_problems.len(),
+            self.translation_problems.join(", ")
+        )?;
+        writeln!(f, "missing images {}: {}", self.missing_images.len(), self.missing_images.join(", "))?;
+        write!(f, "invalid images {}: {}", self.invalid_images.len(), self.invalid_images.join(", "))
+    }
+}
+
+pub enum ImageProblem {
+    Missing,
+    Invalid(String),
+}
+
+//the image is a file telegram accepts as a photo
+pub fn check_image(path: &Path) -> Result<(), ImageProblem> {
+    let size = path.metadata().map_err(|_| ImageProblem::Missing)?.len();
+    if size > MAX_PHOTO_SIZE {
+        return Err(ImageProblem::Invalid(format!("{} bytes", size)));
+    }
+    let reader = image::io::Reader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| ImageProblem::Invalid(format!("can't read: {}", e)))?;
+    match reader.format() {
+        Some(format) if PHOTO_FORMATS.contains(&format) => {}
+        Some(format) => return Err(ImageProblem::Invalid(format!("{:?} format", format))),
+        None => return Err(ImageProblem::Invalid("unknown format".to_string())),
+    }
+    let (width, height) = reader
+        .into_dimensions()
+        .map_err(|e| ImageProblem::Invalid(format!("can't read: {}", e)))?;
+    if width + height > MAX_PHOTO_DIMENSIONS_SUM
+        || width > height * MAX_PHOTO_RATIO
+        || height > width * MAX_PHOTO_RATIO
+    {
+        return Err(ImageProblem::Invalid(format!("{}x{}", width, height)));
+    }
+    Ok(())
+}
+
+//the default language has the keys the bot uses and other languages match it,
+//every card of every deck has a valid image in every language it is translated to
+pub async fn check(translation: &Translation, pictures_path: &str, storage: &dyn Storage) -> Result<CheckReport> {
+    let mut report = CheckReport {
+        translation_problems: translation.problems().to_vec(),
+        ..Default::default()
+    };
+    let language = translation.default_language();
+    for key in translations::MESSAGE_KEYS.iter().chain(&translations::BUTTON_KEYS) {
+        if translation.get(key, language).is_err() {
+            report.missing_keys.push(format!("{}: {}", language, key));
+        }
+    }
+    let mut checked = BTreeSet::new();
+    for deck in storage.get_decks().await? {
+        for card in storage.get_deck_cards(deck.id).await? {
+            for card_translation in &card.translations {
+                let image_path = file_ids::image_path(&deck.path, &card_translation.language, &card.filename);
+                if !checked.insert(image_path.clone()) {
+                    continue;
+                }
+                match check_image(&Path::new(pictures_path).join(&image_path)) {
+                    Ok(()) => {}
+                    Err(ImageProblem::Missing) => report.missing_images.push(image_path),
+                    Err(ImageProblem::Invalid(reason)) => {
+                        report.invalid_images.push(format!("{} ({})", image_path, reason))
+                    }
+                }
+            }
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,298 @@
+use std::{fmt, net::SocketAddr, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use teloxide::{types::InputFile, update_listeners::webhooks};
+use url::Url;
+
+use crate::error::Error;
+use crate::storage;
+
+//every field can be overridden by an environment variable with this prefix,
+//e.g. DECKBOT_BOT_TOKEN or DECKBOT_WEBHOOK_URL
+pub const ENV_PREFIX: &str = "DECKBOT_";
+
+const REDACTED: &str = "***";
+
+//the fields environment variables can set, other variables with the prefix are ignored
+pub(crate) const ENV_FIELDS: [&str; 10] = [
+    "database_path",
+    "bot_token",
+    "database_max_connections",
+    "pictures_path",
+    "default_language",
+    "log_level",
+    "admin_chat_ids",
+    "keyboard_on_photo",
+    "storage_chat_id",
+    "startup_check",
+];
+pub(crate) const ENV_WEBHOOK_FIELDS: [&str; 4] = ["address", "url", "secret_token", "certificate_path"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub database_path: String,
+    pub bot_token: String,
+    #[serde(default = "default_database_max_connections", deserialize_with = "number_or_string")]
+    pub database_max_connections: u32,
+    //the folder with translations, pictures and decks
+    #[serde(default = "default_pictures_path")]
+    pub pictures_path: String,
+    #[serde(default = "default_language")]
+    pub default_language: String,
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    //chats allowed to use admin commands
+    #[serde(default, deserialize_with = "list_or_string")]
+    pub admin_chat_ids: Vec<i64>,
+    //the keyboard is attached to the photo of a drawn card instead of a separate message
+    #[serde(default, deserialize_with = "bool_or_string")]
+    pub keyboard_on_photo: bool,
+    //the chat warmup uploads the card images to, e.g. a private channel with the bot
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub storage_chat_id: Option<i64>,
+    //what to do on start if translations or deck images have problems
+    #[serde(default)]
+    pub startup_check: StartupCheck,
+    //long polling is used, unless webhook is configured
+    pub webhook: Option<WebhookConfig>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartupCheck {
+    //the problems are logged and the bot starts
+    #[default]
+    Warn,
+    //the bot doesn't start
+    Refuse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    //the address to listen on, e.g. 127.0.0.1:8443 behind a reverse proxy
+    pub address: SocketAddr,
+    //the public url Telegram sends updates to, every bot gets them at url/<bot id>
+    pub url: String,
+    pub secret_token: Option<String>,
+    //path to the public key of a self-signed certificate
+    pub certificate_path: Option<String>,
+}
+
+impl WebhookConfig {
+    pub fn options(&self, bot_id: i32) -> Result<webhooks::Options> {
+        let mut url = Url::parse(&self.url)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("webhook.url {} must be an http or https url!", self.url))?
+            .pop_if_empty()
+            .push(&bot_id.to_string());
+        let mut options = webhooks::Options::new(self.address, url);
+        if let Some(secret_token) = &self.secret_token {
+            options = options.secret_token(secret_token.clone());
+        }
+        if let Some(certificate_path) = &self.certificate_path {
+            options = options.certificate(InputFile::file(certificate_path));
+        }
+        Ok(options)
+    }
+}
+
+impl Config {
+    //the format is chosen by the file extension: json, yaml or toml
+    pub fn load(file_name: &str) -> Result<Config> {
+        let file_string = std::fs::read_to_string(file_name)
+            .map_err(|e| anyhow!("Can't read config file {}: {}", file_name, e))?;
+        let extension = Path::new(file_name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("json");
+        let mut value: Value = match extension {
+            "json" => serde_json::from_str(&file_string)?,
+            "yml" | "yaml" => serde_yaml::from_str(&file_string)?,
+            "toml" => serde_json::to_value(toml::from_str::<toml::Value>(&file_string)?)?,
+            other => return Err(anyhow!("Unknown config format {}!", other)),
+        };
+        let object = value
+            .as_object_mut()
+            .ok_or(anyhow!("Config file {} is not a map!", file_name))?;
+        for name in apply_env_overrides(object, std::env::vars())? {
+            //tracing is set up with the log level of the config, so it's not there yet
+            eprintln!("Ignoring unknown environment variable {}", name);
+        }
+        let config: Config = serde_json::from_value(value)
+            .map_err(|e| anyhow!("Wrong config in {}: {}", file_name, e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let mut errors = vec![];
+        match Url::parse(&self.database_path) {
+            Ok(url) if storage::SCHEMES.contains(&url.scheme()) => {}
+            Ok(url) => errors.push(format!("database_path: unsupported database {}", url.scheme())),
+            Err(e) => errors.push(format!("database_path: {}", e)),
+        }
+        if self.bot_token.trim().is_empty() {
+            errors.push("bot_token: must not be empty".to_string());
+        }
+        if self.database_max_connections == 0 {
+            errors.push("database_max_connections: must be at least 1".to_string());
+        }
+        if !Path::new(&self.pictures_path).is_dir() {
+            errors.push(format!("pictures_path: {} is not a folder", self.pictures_path));
+        } else if !Path::new(&self.pictures_path)
+            .join(&self.default_language)
+            .join("translation.yml")
+            .is_file()
+        {
+            errors.push(format!(
+                "default_language: no translation.yml for {} in {}",
+                self.default_language, self.pictures_path
+            ));
+        }
+        if self.log_level.parse::<tracing::Level>().is_err() {
+            errors.push(format!("log_level: unknown level {}", self.log_level));
+        }
+        if let Some(webhook) = &self.webhook {
+            match Url::parse(&webhook.url) {
+                Ok(url) if ["http", "https"].contains(&url.scheme()) => {}
+                Ok(url) => errors.push(format!("webhook.url: unsupported scheme {}", url.scheme())),
+                Err(e) => errors.push(format!("webhook.url: {}", e)),
+            }
+            if let Some(certificate_path) = &webhook.certificate_path {
+                if !Path::new(certificate_path).is_file() {
+                    errors.push(format!("webhook.certificate_path: {} is not a file", certificate_path));
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Config(format!("Wrong config:\n{}", errors.join("\n"))).into())
+        }
+    }
+
+    //the effective config with secrets hidden, to be printed
+    pub fn redacted(&self) -> Config {
+        let mut config = self.clone();
+        config.bot_token = REDACTED.to_string();
+        if let Ok(mut url) = Url::parse(&config.database_path) {
+            if url.password().is_some() && url.set_password(Some(REDACTED)).is_ok() {
+                config.database_path = url.to_string();
+            }
+        }
+        if let Some(webhook) = &mut config.webhook {
+            if webhook.secret_token.is_some() {
+                webhook.secret_token = Some(REDACTED.to_string());
+            }
+        }
+        config
+    }
+}
+
+//DECKBOT_SOME_FIELD sets some_field, DECKBOT_WEBHOOK_SOME_FIELD sets some_field of webhook,
+//returns the names of the variables with the prefix which are not fields
+pub(crate) fn apply_env_overrides(
+    object: &mut Map<String, Value>,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<Vec<String>> {
+    let mut ignored = vec![];
+    for (name, value) in vars {
+        let key = match name.strip_prefix(ENV_PREFIX) {
+            Some(key) => key.to_lowercase(),
+            None => continue,
+        };
+        match key.strip_prefix("webhook_") {
+            Some(webhook_key) if ENV_WEBHOOK_FIELDS.contains(&webhook_key) => {
+                let webhook = object
+                    .entry("webhook")
+                    .or_insert_with(|| Value::Object(Map::new()))
+                    .as_object_mut()
+                    .ok_or(anyhow!("webhook in config is not a map!"))?;
+                webhook.insert(webhook_key.to_string(), Value::String(value));
+            }
+            _ if ENV_FIELDS.contains(&key.as_str()) => {
+                object.insert(key, Value::String(value));
+            }
+            _ => ignored.push(name),
+        }
+    }
+    Ok(ignored)
+}
+
+//numbers from environment variables come as strings
+fn number_or_string<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: fmt::Display,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString<T> {
+        Number(T),
+        String(String),
+    }
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(number) => Ok(number),
+        NumberOrString::String(string) => string.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+fn optional_number_or_string<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: fmt::Display,
+{
+    number_or_string(deserializer).map(Some)
+}
+
+//booleans from environment variables come as strings
+fn bool_or_string<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    number_or_string(deserializer)
+}
+
+//lists from environment variables come as comma separated strings
+fn list_or_string<'de, D>(deserializer: D) -> Result<Vec<i64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ListOrString {
+        List(Vec<i64>),
+        String(String),
+    }
+    match ListOrString::deserialize(deserializer)? {
+        ListOrString::List(list) => Ok(list),
+        ListOrString::String(string) => string
+            .split(',')
+            .map(|item| item.trim())
+            .filter(|item| !item.is_empty())
+            .map(|item| item.parse().map_err(serde::de::Error::custom))
+            .collect(),
+    }
+}
+
+fn default_database_max_connections() -> u32 {
+    5
+}
+
+fn default_pictures_path() -> String {
+    "pictures".to_string()
+}
+
+fn default_language() -> String {
+    "en".to_string()
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
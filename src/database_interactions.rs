@@ -1,37 +1,44 @@
-use anyhow::Result;
-use rand::seq::IteratorRandom;
+use anyhow::{anyhow, Result};
 use sqlx::{postgres::PgPoolOptions, PgPool};
-use std::{fs::File, time::SystemTime};
-use time::{OffsetDateTime};
+use std::{path::Path, time::SystemTime};
+use time::{Date, OffsetDateTime, Time};
 
-use crate::process::{Card, CardData, self};
+use crate::admin::{ChatState, Stats};
+use crate::deck_files;
+use crate::file_ids::{self, FileId};
+use crate::markup;
+use crate::process::{Card, CardData, DailyCardSubscriber, Deck, Draw, self};
+use crate::storage::BotInfo;
 
-pub async fn pool(database_path: String) -> Result<PgPool> {
+pub async fn pool(database_path: String, max_connections: u32) -> Result<PgPool> {
     let pool = PgPoolOptions::new()
-        .max_connections(5)
+        .max_connections(max_connections)
         .connect(&database_path)
         .await?;
     Ok(pool)
 }
 
-pub async fn get_active_bot_id(pool: &PgPool) -> Result<i32> {
-    let query_result = sqlx::query_scalar!(
+pub async fn get_active_bots(pool: &PgPool) -> Result<Vec<BotInfo>> {
+    let bots = sqlx::query_as!(
+        BotInfo,
         "
-        SELECT id FROM bots WHERE active=true
+        SELECT id, name, key FROM bots WHERE active=true
+        ORDER BY id;
     "
     )
-    .fetch_one(pool)
+    .fetch_all(pool)
     .await?;
-    Ok(query_result)
+    Ok(bots)
 }
 
-pub async fn get_subscriber_id(chat_id: String, pool: &PgPool) -> Result<Option<i32>> {
+pub async fn get_subscriber_id(chat_id: String, bot_id: i32, pool: &PgPool) -> Result<Option<i32>> {
     let query_result = sqlx::query_scalar!(
         "
         SELECT id FROM subscribers 
-        WHERE chat_id=$1;
+        WHERE chat_id=$1 AND user_id IS NULL AND bot_id=$2;
     ",
-        chat_id
+        chat_id,
+        bot_id
     )
     .fetch_optional(pool)
     .await?;
@@ -40,16 +47,16 @@ pub async fn get_subscriber_id(chat_id: String, pool: &PgPool) -> Result<Option<
 
 pub async fn get_language(subscriber_id: i32, pool: &PgPool) -> Result<process::Language> {
     let query_result = sqlx::query_scalar!( 
-        r#"
-        SELECT language AS "language: process::Language"
+        "
+        SELECT language
         FROM chats_states 
         WHERE subscriber_id=$1;
-    "#,
+    ",
         subscriber_id
     )
     .fetch_one(pool)
     .await?;
-    Ok(query_result)
+    Ok(process::Language::new(&query_result))
 }
 
 pub async fn get_descriptions_format(subscriber_id: i32, pool: &PgPool) -> Result<i32> {
@@ -65,21 +72,38 @@ pub async fn get_descriptions_format(subscriber_id: i32, pool: &PgPool) -> Resul
     Ok(query_result)
 }
 
-pub async fn create_subscriber(bot_id: i32, chat_id: String, pool: &PgPool) -> Result<i32> {
+pub async fn create_subscriber(
+    bot_id: i32,
+    chat_id: String,
+    language: &process::Language,
+    pool: &PgPool,
+) -> Result<i32> {
     let system_time = SystemTime::now();
     let t: OffsetDateTime = system_time.into();
     let mut tx = pool.begin().await?;
     let subscriber_id = sqlx::query_scalar!(
         "
-            INSERT INTO subscribers (chat_id, created_at) 
-            values ($1, $2)
+            INSERT INTO subscribers (bot_id, chat_id, created_at) 
+            values ($1, $2, $3)
+            ON CONFLICT DO NOTHING
             RETURNING id;
         ",
+        bot_id,
         chat_id,
         t
     )
-    .fetch_one(&mut tx)
+    .fetch_optional(&mut tx)
     .await?;
+    let subscriber_id = match subscriber_id {
+        Some(subscriber_id) => subscriber_id,
+        //another message of the chat has created it meanwhile
+        None => {
+            tx.rollback().await?;
+            return get_subscriber_id(chat_id, bot_id, pool)
+                .await?
+                .ok_or(anyhow!("The subscriber is neither created nor found!"));
+        }
+    };
     sqlx::query!(
         "
             INSERT INTO bots_subscribers (bot_id, subscriber_id) 
@@ -94,11 +118,12 @@ pub async fn create_subscriber(bot_id: i32, chat_id: String, pool: &PgPool) -> R
         "
             INSERT INTO chats_states 
             (bot_id, subscriber_id, drawn_cards, language, descriptions_format) 
-            values ($1, $2, $3, 'en', $4);
+            values ($1, $2, $3, $4, $5);
         ",
         bot_id,
         subscriber_id,
         &[],
+        language.code(),
         process::FULL_DESCRIPTIONS
     )
     .execute(&mut tx)
@@ -107,97 +132,498 @@ pub async fn create_subscriber(bot_id: i32, chat_id: String, pool: &PgPool) -> R
     Ok(subscriber_id)
 }
 
-pub async fn set_description(value: i32, subscriber_id: i32, pool: &PgPool) -> Result<()> {
-    let active_bot_id = get_active_bot_id(pool).await?;
+pub async fn get_member_id(chat_id: String, user_id: i64, bot_id: i32, pool: &PgPool) -> Result<Option<i32>> {
+    let query_result = sqlx::query_scalar!(
+        "
+        SELECT id FROM subscribers 
+        WHERE chat_id=$1 AND user_id=$2 AND bot_id=$3;
+    ",
+        chat_id,
+        user_id,
+        bot_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(query_result)
+}
+
+//a member of a group chat gets the deck settings of the chat
+pub async fn create_member(
+    bot_id: i32,
+    chat_id: String,
+    user_id: i64,
+    subscriber_id: i32,
+    pool: &PgPool,
+) -> Result<i32> {
+    let system_time = SystemTime::now();
+    let t: OffsetDateTime = system_time.into();
+    let mut tx = pool.begin().await?;
+    let member_id = sqlx::query_scalar!(
+        "
+            INSERT INTO subscribers (bot_id, chat_id, user_id, created_at) 
+            values ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING
+            RETURNING id;
+        ",
+        bot_id,
+        chat_id,
+        user_id,
+        t
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+    let member_id = match member_id {
+        Some(member_id) => member_id,
+        //another message of the member has created it meanwhile
+        None => {
+            tx.rollback().await?;
+            return get_member_id(chat_id, user_id, bot_id, pool)
+                .await?
+                .ok_or(anyhow!("The member is neither created nor found!"));
+        }
+    };
     sqlx::query!(
         "
-            UPDATE chats_states SET descriptions_format=$1 
+            INSERT INTO bots_subscribers (bot_id, subscriber_id) 
+            values ($1, $2);
+        ",
+        bot_id,
+        member_id
+    )
+    .execute(&mut tx)
+    .await?;
+    sqlx::query!(
+        "
+            INSERT INTO chats_states 
+            (bot_id, subscriber_id, drawn_cards, language, descriptions_format, deck_id, reversed_cards) 
+            SELECT bot_id, $1, $2, language, descriptions_format, deck_id, reversed_cards
+            FROM chats_states WHERE subscriber_id = $3;
+        ",
+        member_id,
+        &[],
+        subscriber_id
+    )
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(member_id)
+}
+
+pub async fn get_per_member_decks(subscriber_id: i32, pool: &PgPool) -> Result<bool> {
+    let query_result = sqlx::query_scalar!(
+        "
+        SELECT per_member_decks FROM chats_states 
+        WHERE subscriber_id=$1;
+    ",
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(query_result)
+}
+
+pub async fn set_per_member_decks(value: bool, subscriber_id: i32, bot_id: i32, pool: &PgPool) -> Result<()> {
+    sqlx::query!(
+        "
+            UPDATE chats_states SET per_member_decks=$1 
             WHERE subscriber_id=$2 AND bot_id=$3;
         ",
         value,
         subscriber_id,
-        active_bot_id
+        bot_id
     )
     .execute(pool)
     .await?;
     Ok(())
 }
 
-pub async fn set_language(language: process::Language, subscriber_id: i32, pool: &PgPool) -> Result<()> {
-    let active_bot_id = get_active_bot_id(pool).await?;
+pub async fn set_description(value: i32, subscriber_id: i32, bot_id: i32, pool: &PgPool) -> Result<()> {
     sqlx::query!(
         "
-            UPDATE chats_states SET language=$1 
+            UPDATE chats_states SET descriptions_format=$1 
             WHERE subscriber_id=$2 AND bot_id=$3;
         ",
-        language as process::Language,
+        value,
         subscriber_id,
-        active_bot_id
+        bot_id
     )
     .execute(pool)
     .await?;
     Ok(())
 }
 
-pub async fn set_file_id(filename: String, id: String, pool: &PgPool) -> Result<()> {
+//the members of a group chat get the setting too
+pub async fn set_reversed_cards(value: bool, subscriber_id: i32, bot_id: i32, pool: &PgPool) -> Result<()> {
     sqlx::query!(
         "
-            UPDATE cards
-            SET telegram_file_id_en = $1
-            WHERE filename=$2;
+            UPDATE chats_states SET reversed_cards=$1 
+            WHERE bot_id=$3 AND subscriber_id IN (
+                SELECT id FROM subscribers
+                WHERE chat_id = (SELECT chat_id FROM subscribers WHERE id = $2)
+            );
         ",
-        id,
-        filename
+        value,
+        subscriber_id,
+        bot_id
     )
     .execute(pool)
     .await?;
     Ok(())
 }
 
-pub async fn shuffle_cards_back(subscriber_id: i32, pool: &PgPool) -> Result<()> {
-    sqlx::query!(
+pub async fn get_daily_card(subscriber_id: i32, pool: &PgPool) -> Result<(Option<Time>, i32)> {
+    let query_result = sqlx::query!(
         "
-        UPDATE chats_states
-        SET drawn_cards = $1
-        WHERE subscriber_id = $2;
+        SELECT daily_card_time, daily_card_utc_offset FROM chats_states 
+        WHERE subscriber_id=$1;
     ",
-        &[],
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok((query_result.daily_card_time, query_result.daily_card_utc_offset))
+}
+
+//time is local for the utc offset in minutes, no time means no daily card
+pub async fn set_daily_card(
+    time: Option<Time>,
+    utc_offset: i32,
+    subscriber_id: i32,
+    bot_id: i32,
+    pool: &PgPool,
+) -> Result<()> {
+    sqlx::query!(
+        "
+            UPDATE chats_states SET daily_card_time=$1, daily_card_utc_offset=$2 
+            WHERE subscriber_id=$3 AND bot_id=$4;
+        ",
+        time,
+        utc_offset,
+        subscriber_id,
+        bot_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_daily_card_subscribers(bot_id: i32, pool: &PgPool) -> Result<Vec<DailyCardSubscriber>> {
+    let subscribers = sqlx::query_as!(
+        DailyCardSubscriber,
+        r#"
+            SELECT subscribers.id AS subscriber_id, subscribers.chat_id,
+            chats_states.daily_card_time AS "daily_card_time!",
+            chats_states.daily_card_utc_offset, chats_states.daily_card_sent_on
+            FROM chats_states
+            JOIN subscribers ON subscribers.id = chats_states.subscriber_id
+            WHERE chats_states.bot_id = $1 AND chats_states.daily_card_time IS NOT NULL;
+        "#,
+        bot_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(subscribers)
+}
+
+//returns false if the daily card for this date is already sent,
+//so it's never sent twice even if several processes check it at once
+pub async fn mark_daily_card_sent(subscriber_id: i32, date: Date, pool: &PgPool) -> Result<bool> {
+    let result = sqlx::query!(
+        "
+            UPDATE chats_states SET daily_card_sent_on=$1 
+            WHERE subscriber_id=$2 
+            AND (daily_card_sent_on IS NULL OR daily_card_sent_on < $1);
+        ",
+        date,
         subscriber_id
     )
     .execute(pool)
     .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn set_language(language: &process::Language, subscriber_id: i32, bot_id: i32, pool: &PgPool) -> Result<()> {
+    sqlx::query!(
+        "
+            UPDATE chats_states SET language=$1 
+            WHERE subscriber_id=$2 AND bot_id=$3;
+        ",
+        language.code(),
+        subscriber_id,
+        bot_id
+    )
+    .execute(pool)
+    .await?;
     Ok(())
 }
 
-pub async fn random_card_file_name(
-    language: process::Language,
-    descriptions: i32,
-    subscriber_id: i32,
+pub async fn search_cards(
+    text: &str,
+    language: &process::Language,
+    deck_id: i32,
+    bot_id: i32,
     pool: &PgPool,
-) -> Result<Option<CardData>> {
-    let cards: Vec<Card> = sqlx::query_as!(
+) -> Result<Vec<Card>> {
+    let cards = sqlx::query_as!(
         Card,
+        r#"
+            SELECT cards.id, cards.filename, card_translations.name,
+            card_translations.description, file_ids.file_id AS "telegram_file_id?",
+            card_translations.reversed_name, card_translations.reversed_description,
+            reversed_file_ids.file_id AS "reversed_telegram_file_id?", cards.weight
+            FROM cards
+            JOIN card_translations ON card_translations.card_id = cards.id
+            LEFT JOIN file_ids ON file_ids.card_id = cards.id
+            AND file_ids.language = card_translations.language
+            AND file_ids.bot_id = $4 AND NOT file_ids.reversed
+            LEFT JOIN file_ids AS reversed_file_ids ON reversed_file_ids.card_id = cards.id
+            AND reversed_file_ids.language = card_translations.language
+            AND reversed_file_ids.bot_id = $4 AND reversed_file_ids.reversed
+            WHERE cards.deck_id = $1 AND card_translations.language = $2
+            AND strpos(lower(card_translations.name), lower($3)) > 0
+            ORDER BY card_translations.name;
+        "#,
+        deck_id,
+        language.code(),
+        text,
+        bot_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(cards)
+}
+
+pub async fn set_file_id(
+    card_id: i32,
+    language: &process::Language,
+    reversed: bool,
+    id: String,
+    content_hash: String,
+    bot_id: i32,
+    pool: &PgPool,
+) -> Result<()> {
+    sqlx::query!(
         "
-            SELECT * FROM cards;
+            INSERT INTO file_ids (bot_id, card_id, language, reversed, file_id, content_hash)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (bot_id, card_id, language, reversed) DO UPDATE SET
+            file_id = EXCLUDED.file_id, content_hash = EXCLUDED.content_hash;
+        ",
+        bot_id,
+        card_id,
+        language.code(),
+        reversed,
+        id,
+        content_hash
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+//file ids of the bot with the paths of their images
+pub async fn get_file_ids(bot_id: i32, pool: &PgPool) -> Result<Vec<FileId>> {
+    let rows = sqlx::query!(
         "
+            SELECT file_ids.card_id, file_ids.language, file_ids.reversed,
+            file_ids.content_hash, cards.filename, decks.path
+            FROM file_ids
+            JOIN cards ON cards.id = file_ids.card_id
+            JOIN decks ON decks.id = cards.deck_id
+            WHERE file_ids.bot_id = $1
+            ORDER BY file_ids.card_id;
+        ",
+        bot_id
     )
     .fetch_all(pool)
     .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| FileId {
+            card_id: row.card_id,
+            image_path: file_ids::image_path(&row.path, &row.language, &row.filename),
+            language: process::Language::new(&row.language),
+            reversed: row.reversed,
+            content_hash: row.content_hash,
+        })
+        .collect())
+}
 
-    let mut skip_cards_ids: Vec<i32> = sqlx::query!(
+pub async fn delete_file_id(
+    card_id: i32,
+    language: &process::Language,
+    reversed: bool,
+    bot_id: i32,
+    pool: &PgPool,
+) -> Result<()> {
+    sqlx::query!(
         "
-            SELECT drawn_cards FROM chats_states WHERE subscriber_id = $1;
+            DELETE FROM file_ids
+            WHERE bot_id = $1 AND card_id = $2 AND language = $3 AND reversed = $4;
         ",
+        bot_id,
+        card_id,
+        language.code(),
+        reversed
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_reversed_cards_decks(bot_id: i32, pool: &PgPool) -> Result<Vec<i32>> {
+    let rows = sqlx::query!(
+        "
+            SELECT DISTINCT deck_id FROM chats_states
+            WHERE bot_id = $1 AND reversed_cards
+            ORDER BY deck_id;
+        ",
+        bot_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|row| row.deck_id).collect())
+}
+
+pub async fn get_decks(pool: &PgPool) -> Result<Vec<Deck>> {
+    let decks = sqlx::query_as!(
+        Deck,
+        r#"
+            SELECT id, name, path, with_replacement, markup AS "markup: markup::Format"
+            FROM decks ORDER BY id;
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(decks)
+}
+
+pub async fn get_deck(subscriber_id: i32, pool: &PgPool) -> Result<Deck> {
+    let deck = sqlx::query_as!(
+        Deck,
+        r#"
+            SELECT decks.id, decks.name, decks.path, decks.with_replacement,
+            decks.markup AS "markup: markup::Format" FROM decks
+            JOIN chats_states ON chats_states.deck_id = decks.id
+            WHERE chats_states.subscriber_id = $1;
+        "#,
         subscriber_id
     )
     .fetch_one(pool)
-    .await?
-    .drawn_cards;
+    .await?;
+    Ok(deck)
+}
 
-    let chosen_card = cards.iter().filter(|c| !skip_cards_ids.contains(&c.id)).choose(&mut rand::thread_rng());
-    if let Some(chosen_card) = chosen_card {
-        //save drawn card to database
-        skip_cards_ids.push(chosen_card.id);
+//the members of a group chat get the deck too
+pub async fn set_deck(deck_id: i32, subscriber_id: i32, bot_id: i32, pool: &PgPool) -> Result<()> {
+    sqlx::query!(
+        "
+            UPDATE chats_states SET deck_id=$1 
+            WHERE bot_id=$3 AND subscriber_id IN (
+                SELECT id FROM subscribers
+                WHERE chat_id = (SELECT chat_id FROM subscribers WHERE id = $2)
+            );
+        ",
+        deck_id,
+        subscriber_id,
+        bot_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+//only the cards of the selected deck are shuffled back,
+//drawn cards of the other decks stay drawn
+pub async fn shuffle_cards_back(subscriber_id: i32, pool: &PgPool) -> Result<()> {
+    sqlx::query!(
+        "
+        UPDATE chats_states
+        SET drawn_cards = ARRAY(
+            SELECT card_id FROM unnest(drawn_cards) AS card_id
+            WHERE card_id NOT IN (SELECT id FROM cards WHERE deck_id = chats_states.deck_id)
+        )
+        WHERE subscriber_id = $1;
+    ",
+        subscriber_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+//draws `count` distinct cards which are not drawn yet,
+//either all of them or none if there are not enough cards left,
+//each card is paired with whether it's drawn reversed,
+//the draws are saved to history with their positions in the spread
+pub async fn draw_cards(
+    count: usize,
+    spread_id: Option<&str>,
+    language: &process::Language,
+    subscriber_id: i32,
+    bot_id: i32,
+    pool: &PgPool,
+) -> Result<Option<(Deck, Vec<(Card, bool)>)>> {
+    let mut tx = pool.begin().await?;
+    //lock the row, so parallel draws don't get the same cards
+    let chat_state = sqlx::query!(
+        "
+            SELECT drawn_cards, deck_id, reversed_cards
+            FROM chats_states WHERE subscriber_id = $1 FOR UPDATE;
+        ",
+        subscriber_id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+    let mut skip_cards_ids = chat_state.drawn_cards;
+
+    let deck = sqlx::query_as!(
+        Deck,
+        r#"
+            SELECT id, name, path, with_replacement, markup AS "markup: markup::Format"
+            FROM decks WHERE id = $1;
+        "#,
+        chat_state.deck_id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    let cards: Vec<Card> = sqlx::query_as!(
+        Card,
+        r#"
+            SELECT cards.id, cards.filename, card_translations.name,
+            card_translations.description, file_ids.file_id AS "telegram_file_id?",
+            card_translations.reversed_name, card_translations.reversed_description,
+            reversed_file_ids.file_id AS "reversed_telegram_file_id?", cards.weight
+            FROM cards
+            JOIN card_translations ON card_translations.card_id = cards.id
+            LEFT JOIN file_ids ON file_ids.card_id = cards.id
+            AND file_ids.language = card_translations.language
+            AND file_ids.bot_id = $3 AND NOT file_ids.reversed
+            LEFT JOIN file_ids AS reversed_file_ids ON reversed_file_ids.card_id = cards.id
+            AND reversed_file_ids.language = card_translations.language
+            AND reversed_file_ids.bot_id = $3 AND reversed_file_ids.reversed
+            WHERE cards.deck_id = $1 AND card_translations.language = $2;
+        "#,
+        deck.id,
+        language.code(),
+        bot_id
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    let cards: Vec<Card> = cards
+        .into_iter()
+        .filter(|c| deck.with_replacement || !skip_cards_ids.contains(&c.id))
+        .collect();
+    let chosen_cards = match process::choose_cards(cards, count) {
+        Some(chosen_cards) => chosen_cards,
+        None => return Ok(None),
+    };
+
+    //save drawn cards to database, decks with replacement don't put them aside
+    if !deck.with_replacement {
+        skip_cards_ids.extend(chosen_cards.iter().map(|c| c.id));
         sqlx::query!(
             "
                 UPDATE chats_states 
@@ -207,67 +633,438 @@ pub async fn random_card_file_name(
             &skip_cards_ids,
             subscriber_id
         )
-        .execute(pool)
+        .execute(&mut tx)
         .await?;
+    }
 
-        let file_id = chosen_card.get_file_id(language);
-        let message_text = match descriptions {
-            process::FULL_DESCRIPTIONS => {
-                let name = chosen_card.get_name(language);
-                let description = chosen_card.get_description(language);
-                format!("{}\n{}", name, description)
-            }
-            process::NAMES_ONLY => {
-                let name = chosen_card.get_name(language);
-                name
-            },
-            process::NO_DESCRIPTIONS => "".to_string(),
-            _ => {
-                panic!("Descriptions format not supported!");
-            } 
-        };
-
-        Ok(Some(CardData {
-            filename: chosen_card.filename.clone(),
-            message_text,
-            file_id,
-        }))
-    } else {
-        Ok(None)
+    let chosen_cards: Vec<(Card, bool)> = chosen_cards
+        .into_iter()
+        .map(|card| {
+            let reversed = chat_state.reversed_cards && rand::random::<bool>();
+            (card, reversed)
+        })
+        .collect();
+    let t: OffsetDateTime = SystemTime::now().into();
+    for (position, (card, reversed)) in chosen_cards.iter().enumerate() {
+        let spread_position = spread_id.map(|_| position as i32);
+        sqlx::query!(
+            "
+                INSERT INTO draws
+                (subscriber_id, card_id, deck_id, reversed, spread, spread_position, drawn_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7);
+            ",
+            subscriber_id,
+            card.id,
+            deck.id,
+            reversed,
+            spread_id,
+            spread_position,
+            t
+        )
+        .execute(&mut tx)
+        .await?;
     }
+    tx.commit().await?;
+    Ok(Some((deck, chosen_cards)))
 }
 
-pub async fn check_cards_table(pool: &PgPool) -> Result<()> {
+pub async fn count_draws(subscriber_id: i32, pool: &PgPool) -> Result<i64> {
     let count = sqlx::query_scalar!(
         "
-            SELECT COUNT(*) FROM cards;
+            SELECT COUNT(*) FROM draws WHERE subscriber_id = $1;
+        ",
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(count.unwrap_or(0))
+}
+
+//the most recent draws first, cards without a translation to the language are shown by file names
+pub async fn get_draws(
+    language: &process::Language,
+    offset: i64,
+    limit: i64,
+    subscriber_id: i32,
+    pool: &PgPool,
+) -> Result<Vec<Draw>> {
+    let draws = sqlx::query_as!(
+        Draw,
+        r#"
+            SELECT draws.id, COALESCE(card_translations.name, cards.filename) AS "name!",
+            draws.reversed, draws.drawn_at
+            FROM draws
+            JOIN cards ON cards.id = draws.card_id
+            LEFT JOIN card_translations ON card_translations.card_id = draws.card_id
+            AND card_translations.language = $2
+            WHERE draws.subscriber_id = $1
+            ORDER BY draws.drawn_at DESC, draws.id DESC
+            OFFSET $3 LIMIT $4;
+        "#,
+        subscriber_id,
+        language.code(),
+        offset,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(draws)
+}
+
+//the card of a draw from history, it's not drawn again
+pub async fn drawn_card(
+    draw_id: i32,
+    language: &process::Language,
+    descriptions: i32,
+    subscriber_id: i32,
+    bot_id: i32,
+    pool: &PgPool,
+) -> Result<Option<CardData>> {
+    let draw = sqlx::query!(
         "
+            SELECT card_id, deck_id, reversed FROM draws
+            WHERE id = $1 AND subscriber_id = $2;
+        ",
+        draw_id,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    let draw = match draw {
+        Some(draw) => draw,
+        None => return Ok(None),
+    };
+    let deck = sqlx::query_as!(
+        Deck,
+        r#"
+            SELECT id, name, path, with_replacement, markup AS "markup: markup::Format"
+            FROM decks WHERE id = $1;
+        "#,
+        draw.deck_id
     )
     .fetch_one(pool)
     .await?;
-    if count == Some(0) {
-        //fill the table from csv file
-        let file = File::open("pictures/data.csv").unwrap();
-        let mut rdr = csv::Reader::from_reader(file);
-        let mut tx = pool.begin().await?;
-        for result in rdr.records() {
-            let record = result?;
+    let card = sqlx::query_as!(
+        Card,
+        r#"
+            SELECT cards.id, cards.filename, card_translations.name,
+            card_translations.description, file_ids.file_id AS "telegram_file_id?",
+            card_translations.reversed_name, card_translations.reversed_description,
+            reversed_file_ids.file_id AS "reversed_telegram_file_id?", cards.weight
+            FROM cards
+            JOIN card_translations ON card_translations.card_id = cards.id
+            LEFT JOIN file_ids ON file_ids.card_id = cards.id
+            AND file_ids.language = card_translations.language
+            AND file_ids.bot_id = $3 AND NOT file_ids.reversed
+            LEFT JOIN file_ids AS reversed_file_ids ON reversed_file_ids.card_id = cards.id
+            AND reversed_file_ids.language = card_translations.language
+            AND reversed_file_ids.bot_id = $3 AND reversed_file_ids.reversed
+            WHERE cards.id = $1 AND card_translations.language = $2;
+        "#,
+        draw.card_id,
+        language.code(),
+        bot_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    card.map(|card| process::card_data(&deck, &card, draw.reversed, language, descriptions))
+        .transpose()
+}
+
+//counts of chats for the bot, members of groups are not counted,
+//"today" starts at midnight UTC
+pub async fn get_stats(bot_id: i32, pool: &PgPool) -> Result<Stats> {
+    let today = OffsetDateTime::now_utc().replace_time(Time::MIDNIGHT);
+    let subscribers = sqlx::query!(
+        r#"
+            SELECT COUNT(*) AS "subscribers!",
+            COUNT(*) FILTER (WHERE subscribers.created_at >= $2) AS "new_subscribers!",
+            COUNT(*) FILTER (WHERE chats_states.daily_card_time IS NOT NULL) AS "daily_card_subscribers!"
+            FROM chats_states
+            JOIN subscribers ON subscribers.id = chats_states.subscriber_id
+            WHERE chats_states.bot_id = $1 AND subscribers.user_id IS NULL;
+        "#,
+        bot_id,
+        today
+    )
+    .fetch_one(pool)
+    .await?;
+    let draws_today = sqlx::query_scalar!(
+        r#"
+            SELECT COUNT(*) AS "count!" FROM draws
+            JOIN chats_states ON chats_states.subscriber_id = draws.subscriber_id
+            WHERE chats_states.bot_id = $1 AND draws.drawn_at >= $2;
+        "#,
+        bot_id,
+        today
+    )
+    .fetch_one(pool)
+    .await?;
+    let languages = sqlx::query!(
+        r#"
+            SELECT language, COUNT(*) AS "count!" FROM chats_states
+            JOIN subscribers ON subscribers.id = chats_states.subscriber_id
+            WHERE chats_states.bot_id = $1 AND subscribers.user_id IS NULL
+            GROUP BY language
+            ORDER BY 2 DESC, language;
+        "#,
+        bot_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| (row.language, row.count))
+    .collect();
+    Ok(Stats {
+        subscribers: subscribers.subscribers,
+        new_subscribers: subscribers.new_subscribers,
+        daily_card_subscribers: subscribers.daily_card_subscribers,
+        draws_today,
+        languages,
+    })
+}
+
+//chat ids of all chats of the bot
+pub async fn get_subscribers_chat_ids(bot_id: i32, pool: &PgPool) -> Result<Vec<String>> {
+    let chat_ids = sqlx::query_scalar!(
+        "
+            SELECT subscribers.chat_id FROM subscribers
+            JOIN bots_subscribers ON bots_subscribers.subscriber_id = subscribers.id
+            WHERE bots_subscribers.bot_id = $1 AND subscribers.user_id IS NULL
+            ORDER BY subscribers.id;
+        ",
+        bot_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(chat_ids)
+}
+
+pub async fn get_chat_state(chat_id: String, bot_id: i32, pool: &PgPool) -> Result<Option<ChatState>> {
+    let chat_state = sqlx::query_as!(
+        ChatState,
+        r#"
+            SELECT subscribers.id AS subscriber_id, subscribers.created_at,
+            chats_states.language, chats_states.descriptions_format,
+            chats_states.reversed_cards, decks.name AS deck_name,
+            cardinality(chats_states.drawn_cards) AS "drawn_cards!",
+            chats_states.daily_card_time, chats_states.daily_card_utc_offset,
+            (SELECT COUNT(*) FROM draws WHERE draws.subscriber_id = subscribers.id) AS "draws!"
+            FROM subscribers
+            JOIN chats_states ON chats_states.subscriber_id = subscribers.id
+            JOIN decks ON decks.id = chats_states.deck_id
+            WHERE subscribers.chat_id = $1 AND subscribers.user_id IS NULL
+            AND chats_states.bot_id = $2;
+        "#,
+        chat_id,
+        bot_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(chat_state)
+}
+
+//decks' paths are relative to pictures_path
+pub async fn check_cards_table(pictures_path: &str, pool: &PgPool) -> Result<()> {
+    check_decks_table(pictures_path, pool).await?;
+    for deck in get_decks(pool).await? {
+        let count = sqlx::query_scalar!(
+            "
+                SELECT COUNT(*) FROM cards WHERE deck_id = $1;
+            ",
+            deck.id
+        )
+        .fetch_one(pool)
+        .await?;
+        if count == Some(0) {
+            //fill the table from csv file
+            let cards = deck_files::read_cards(pictures_path, &deck.path)?;
+            let mut tx = pool.begin().await?;
+            for card in cards {
+                let card_id = sqlx::query_scalar!(
+                    "
+                    INSERT INTO cards 
+                    (filename, deck_id, weight)
+                    VALUES ($1, $2, $3)
+                    RETURNING id;
+                ",
+                    card.filename,
+                    deck.id,
+                    card.weight
+                )
+                .fetch_one(&mut tx)
+                .await?;
+                for card_translation in card.translations {
+                    sqlx::query!(
+                        "
+                        INSERT INTO card_translations 
+                        (card_id, language, name, description, reversed_name, reversed_description)
+                        VALUES ($1, $2, $3, $4, $5, $6);
+                    ",
+                        card_id,
+                        card_translation.language,
+                        card_translation.name,
+                        card_translation.description,
+                        card_translation.reversed_name,
+                        card_translation.reversed_description
+                    )
+                    .execute(&mut tx)
+                    .await?;
+                }
+            }
+            tx.commit().await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn get_deck_cards(deck_id: i32, pool: &PgPool) -> Result<Vec<deck_files::CardRecord>> {
+    let rows = sqlx::query!(
+        "
+            SELECT cards.id, cards.filename, cards.weight, card_translations.language, card_translations.name,
+            card_translations.description, card_translations.reversed_name,
+            card_translations.reversed_description
+            FROM cards
+            JOIN card_translations ON card_translations.card_id = cards.id
+            WHERE cards.deck_id = $1
+            ORDER BY cards.id, card_translations.language;
+        ",
+        deck_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut cards: Vec<(i32, deck_files::CardRecord)> = vec![];
+    for row in rows {
+        let translation = deck_files::CardTranslation {
+            language: row.language,
+            name: row.name,
+            description: row.description,
+            reversed_name: row.reversed_name,
+            reversed_description: row.reversed_description,
+        };
+        match cards.last_mut() {
+            Some((id, card)) if *id == row.id => card.translations.push(translation),
+            _ => cards.push((
+                row.id,
+                deck_files::CardRecord {
+                    filename: row.filename,
+                    weight: row.weight,
+                    translations: vec![translation],
+                },
+            )),
+        }
+    }
+    Ok(cards.into_iter().map(|(_, card)| card).collect())
+}
+
+pub async fn import_deck(
+    name: &str,
+    path: &str,
+    with_replacement: bool,
+    markup: markup::Format,
+    cards: &[deck_files::CardRecord],
+    changed_images: &[(String, String)],
+    pool: &PgPool,
+) -> Result<i32> {
+    let mut tx = pool.begin().await?;
+    let deck_id = sqlx::query_scalar!(
+        "
+            INSERT INTO decks (name, path, with_replacement, markup) 
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (path) DO UPDATE SET
+            name = EXCLUDED.name, with_replacement = EXCLUDED.with_replacement, markup = EXCLUDED.markup
+            RETURNING id;
+        ",
+        name,
+        path,
+        with_replacement,
+        markup as markup::Format
+    )
+    .fetch_one(&mut tx)
+    .await?;
+    for card in cards {
+        let card_id = sqlx::query_scalar!(
+            "
+                INSERT INTO cards (filename, deck_id, weight)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (deck_id, filename) DO UPDATE SET weight = EXCLUDED.weight
+                RETURNING id;
+            ",
+            card.filename,
+            deck_id,
+            card.weight
+        )
+        .fetch_one(&mut tx)
+        .await?;
+        for card_translation in &card.translations {
             sqlx::query!(
                 "
-                INSERT INTO cards 
-                (filename, name_en, description_en, name_ru, description_ru)
-                VALUES ($1, $2, $3, $4, $5);
-            ",
-                record[0].to_string(),
-                record[1].to_string(),
-                record[2].to_string(),
-                record[3].to_string(),
-                record[4].to_string()
+                    INSERT INTO card_translations 
+                    (card_id, language, name, description, reversed_name, reversed_description)
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                    ON CONFLICT (card_id, language) DO UPDATE SET
+                    name = EXCLUDED.name, description = EXCLUDED.description,
+                    reversed_name = EXCLUDED.reversed_name,
+                    reversed_description = EXCLUDED.reversed_description;
+                ",
+                card_id,
+                card_translation.language,
+                card_translation.name,
+                card_translation.description,
+                card_translation.reversed_name,
+                card_translation.reversed_description
             )
             .execute(&mut tx)
             .await?;
         }
-        tx.commit().await?;
     }
+    for (filename, language) in changed_images {
+        sqlx::query!(
+            "
+                DELETE FROM file_ids
+                WHERE language = $1 AND card_id IN (
+                    SELECT id FROM cards WHERE deck_id = $2 AND filename = $3
+                );
+            ",
+            language,
+            deck_id,
+            filename
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(deck_id)
+}
+
+//the main deck in pictures_path itself is created by migrations,
+//the drawing of every deck follows its deck.yml
+async fn check_decks_table(pictures_path: &str, pool: &PgPool) -> Result<()> {
+    for deck_folder in deck_files::deck_folders(pictures_path)? {
+        sqlx::query!(
+            "
+                INSERT INTO decks (name, path, with_replacement, markup) 
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (path) DO UPDATE SET
+                with_replacement = EXCLUDED.with_replacement, markup = EXCLUDED.markup;
+            ",
+            deck_folder.name,
+            deck_folder.path,
+            deck_folder.with_replacement,
+            deck_folder.markup as markup::Format
+        )
+        .execute(pool)
+        .await?;
+    }
+    let main_deck = deck_files::deck_info(Path::new(pictures_path))?;
+    sqlx::query!(
+        "
+            UPDATE decks SET with_replacement = $1, markup = $2 WHERE path = '.';
+        ",
+        main_deck.with_replacement,
+        main_deck.markup as markup::Format
+    )
+    .execute(pool)
+    .await?;
     Ok(())
-}
\ No newline at end of file
+}
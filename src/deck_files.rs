@@ -0,0 +1,177 @@
+use anyhow::{anyhow, Result};
+use std::{fs::File, path::Path};
+
+use crate::markup;
+
+//extra decks are in this folder of pictures_path
+pub const DECKS_PATH: &str = "decks";
+
+//cards are drawn this often compared to the others, unless the weight column says otherwise
+pub const DEFAULT_WEIGHT: i32 = 1;
+
+pub struct DeckFolder {
+    pub name: String,
+    //relative to pictures_path
+    pub path: String,
+    pub with_replacement: bool,
+    pub markup: markup::Format,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CardRecord {
+    pub filename: String,
+    pub weight: i32,
+    pub translations: Vec<CardTranslation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CardTranslation {
+    pub language: String,
+    pub name: String,
+    pub description: String,
+    pub reversed_name: Option<String>,
+    pub reversed_description: Option<String>,
+}
+
+//every folder in DECKS_PATH with a data.csv file is a deck,
+//the main deck in pictures_path itself is not listed
+pub fn deck_folders(pictures_path: &str) -> Result<Vec<DeckFolder>> {
+    let entries = match std::fs::read_dir(Path::new(pictures_path).join(DECKS_PATH)) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(vec![]),
+    };
+    let mut folders = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        if !path.join("data.csv").is_file() {
+            continue;
+        }
+        let path_string = Path::new(DECKS_PATH)
+            .join(path.file_name().unwrap_or_default())
+            .to_string_lossy()
+            .to_string();
+        let deck_info = deck_info(&path)?;
+        let name = match deck_info.name {
+            Some(name) => name,
+            None => path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        };
+        folders.push(DeckFolder {
+            name,
+            path: path_string,
+            with_replacement: deck_info.with_replacement,
+            markup: deck_info.markup,
+        });
+    }
+    Ok(folders)
+}
+
+//deck.yml in the deck folder, the defaults if there is no file
+pub fn deck_info(path: &Path) -> Result<DeckInfo> {
+    match std::fs::read_to_string(path.join("deck.yml")) {
+        Ok(file_string) => Ok(serde_yaml::from_str::<DeckInfo>(&file_string)?),
+        Err(_) => Ok(DeckInfo::default()),
+    }
+}
+
+pub fn write_deck_info(path: &Path, deck_info: &DeckInfo) -> Result<()> {
+    std::fs::write(path.join("deck.yml"), serde_yaml::to_string(deck_info)?)?;
+    Ok(())
+}
+
+//the first column of data.csv is the file name,
+//the others are name_<language> and description_<language>
+//and optional weight, reversed_name_<language> and reversed_description_<language>
+pub fn read_cards(pictures_path: &str, deck_path: &str) -> Result<Vec<CardRecord>> {
+    let file = File::open(Path::new(pictures_path).join(deck_path).join("data.csv"))?;
+    let mut rdr = csv::Reader::from_reader(file);
+    let headers = rdr.headers()?.clone();
+    let languages: Vec<&str> = headers
+        .iter()
+        .filter_map(|header| header.strip_prefix("name_"))
+        .collect();
+    let weight_index = headers.iter().position(|header| header == "weight");
+    let mut cards = vec![];
+    for result in rdr.records() {
+        let record = result?;
+        let weight = match weight_index.and_then(|index| record.get(index)).filter(|w| !w.is_empty()) {
+            Some(weight) => weight
+                .parse()
+                .ok()
+                .filter(|weight| *weight > 0)
+                .ok_or(anyhow!("Wrong weight {} of {} in {}/data.csv!", weight, &record[0], deck_path))?,
+            None => DEFAULT_WEIGHT,
+        };
+        let mut translations = vec![];
+        for language in &languages {
+            let column = |prefix: &str| -> Result<String> {
+                let index = headers
+                    .iter()
+                    .position(|header| header == format!("{}{}", prefix, language))
+                    .ok_or(anyhow!("No {}{} column in {}/data.csv!", prefix, language, deck_path))?;
+                Ok(record.get(index).unwrap_or_default().to_string())
+            };
+            //reversed texts are optional
+            let optional_column = |prefix: &str| -> Option<String> {
+                column(prefix).ok().filter(|value| !value.is_empty())
+            };
+            translations.push(CardTranslation {
+                language: language.to_string(),
+                name: column("name_")?,
+                description: column("description_")?,
+                reversed_name: optional_column("reversed_name_"),
+                reversed_description: optional_column("reversed_description_"),
+            });
+        }
+        cards.push(CardRecord {
+            filename: record[0].to_string(),
+            weight,
+            translations,
+        });
+    }
+    Ok(cards)
+}
+
+//writes data.csv with the columns read_cards expects,
+//languages are taken from the first card
+pub fn write_cards(path: &Path, cards: &[CardRecord]) -> Result<()> {
+    let languages: Vec<&str> = cards
+        .first()
+        .map(|card| card.translations.iter().map(|t| t.language.as_str()).collect())
+        .unwrap_or_default();
+    let mut wtr = csv::Writer::from_path(path.join("data.csv"))?;
+    let mut headers = vec!["file_name".to_string(), "weight".to_string()];
+    for prefix in ["name_", "description_", "reversed_name_", "reversed_description_"] {
+        headers.extend(languages.iter().map(|language| format!("{}{}", prefix, language)));
+    }
+    wtr.write_record(&headers)?;
+    for card in cards {
+        let translation = |language: &str| card.translations.iter().find(|t| t.language == language);
+        let mut record = vec![card.filename.clone(), card.weight.to_string()];
+        for language in &languages {
+            record.push(translation(language).map(|t| t.name.clone()).unwrap_or_default());
+        }
+        for language in &languages {
+            record.push(translation(language).map(|t| t.description.clone()).unwrap_or_default());
+        }
+        for language in &languages {
+            record.push(translation(language).and_then(|t| t.reversed_name.clone()).unwrap_or_default());
+        }
+        for language in &languages {
+            record.push(translation(language).and_then(|t| t.reversed_description.clone()).unwrap_or_default());
+        }
+        wtr.write_record(&record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+pub struct DeckInfo {
+    pub name: Option<String>,
+    //drawn cards are not put aside until shuffled, so they can be drawn again
+    #[serde(default)]
+    pub with_replacement: bool,
+    //the markup format of the card descriptions
+    #[serde(default)]
+    pub markup: markup::Format,
+}
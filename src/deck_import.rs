@@ -0,0 +1,288 @@
+use anyhow::{anyhow, Result};
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::deck_files::{self, CardRecord};
+use crate::markup;
+use crate::storage::Storage;
+
+//what importing a deck folder changes, cards are named by their file names
+pub struct ImportReport {
+    pub deck_name: String,
+    pub deck_path: String,
+    pub with_replacement: bool,
+    pub markup: markup::Format,
+    pub dry_run: bool,
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub unchanged: usize,
+    //cards which are in the database but not in data.csv, they are kept as draws refer to them
+    pub missing: Vec<String>,
+    //(file name, language) of the replaced images, their telegram file ids are forgotten
+    pub changed_images: Vec<(String, String)>,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Deck {} ({}, drawn {} replacement, {:?} descriptions){}:",
+            self.deck_name,
+            self.deck_path,
+            if self.with_replacement { "with" } else { "without" },
+            self.markup,
+            if self.dry_run { ", nothing is changed" } else { "" }
+        )?;
+        writeln!(f, "added {}: {}", self.added.len(), self.added.join(", "))?;
+        writeln!(f, "changed {}: {}", self.changed.len(), self.changed.join(", "))?;
+        writeln!(f, "unchanged {}", self.unchanged)?;
+        writeln!(f, "not in data.csv, kept {}: {}", self.missing.len(), self.missing.join(", "))?;
+        let changed_images: Vec<String> = self
+            .changed_images
+            .iter()
+            .map(|(filename, language)| format!("{}/{}", language, filename))
+            .collect();
+        write!(f, "images replaced {}: {}", changed_images.len(), changed_images.join(", "))
+    }
+}
+
+pub struct ExportReport {
+    pub cards: usize,
+    pub images: usize,
+    //language/file name of the images which are not in pictures_path
+    pub missing_images: Vec<String>,
+}
+
+impl fmt::Display for ExportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Exported {} cards and {} images", self.cards, self.images)?;
+        if !self.missing_images.is_empty() {
+            write!(f, ", missing images: {}", self.missing_images.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+//reads data.csv of the deck folder and checks that every card has its names, images
+//and descriptions in the markup format of deck.yml, all the problems are reported at once
+pub fn check_deck_folder(dir: &Path) -> Result<Vec<CardRecord>> {
+    let dir_string = dir.to_string_lossy();
+    let cards = deck_files::read_cards(&dir_string, ".")?;
+    let format = deck_files::deck_info(dir)?.markup;
+    let mut problems = vec![];
+    if cards.is_empty() {
+        problems.push("no cards in data.csv".to_string());
+    }
+    let mut filenames = HashSet::new();
+    for (index, card) in cards.iter().enumerate() {
+        if card.filename.is_empty() {
+            problems.push(format!("no file name in row {}", index + 1));
+            continue;
+        }
+        if !filenames.insert(card.filename.as_str()) {
+            problems.push(format!("{} is listed twice", card.filename));
+        }
+        if card.translations.is_empty() {
+            problems.push(format!("{} has no name_<language> columns", card.filename));
+        }
+        for translation in &card.translations {
+            if translation.name.is_empty() {
+                problems.push(format!("{} has no name in {}", card.filename, translation.language));
+            }
+            let descriptions = [Some(&translation.description), translation.reversed_description.as_ref()];
+            for description in descriptions.into_iter().flatten() {
+                if let Err(e) = markup::render(description, format) {
+                    problems.push(format!("{} has a malformed description in {}: {}", card.filename, translation.language, e));
+                }
+            }
+            if !dir.join(&translation.language).join(&card.filename).is_file() {
+                problems.push(format!("no image {}/{}", translation.language, card.filename));
+            }
+        }
+    }
+    if problems.is_empty() {
+        Ok(cards)
+    } else {
+        Err(anyhow!("Deck {} is not valid:\n{}", dir_string, problems.join("\n")))
+    }
+}
+
+//decks inside pictures_path are imported in place, others are copied to its DECKS_PATH
+fn deck_path(dir: &Path, pictures_path: &str) -> Result<(String, bool)> {
+    let dir = dir.canonicalize()?;
+    let pictures_path = Path::new(pictures_path).canonicalize()?;
+    if let Ok(relative) = dir.strip_prefix(&pictures_path) {
+        let path = relative.to_string_lossy().to_string();
+        return Ok((if path.is_empty() { ".".to_string() } else { path }, true));
+    }
+    let folder = dir.file_name().ok_or(anyhow!("Wrong deck folder {}!", dir.display()))?;
+    let path = Path::new(deck_files::DECKS_PATH).join(folder);
+    Ok((path.to_string_lossy().to_string(), false))
+}
+
+//validates the deck folder, then upserts the deck and its cards by file names,
+//with dry_run only reports what would change
+pub async fn import(dir: &Path, pictures_path: &str, storage: &dyn Storage, dry_run: bool) -> Result<ImportReport> {
+    let mut cards = check_deck_folder(dir)?;
+    for card in &mut cards {
+        card.translations.sort_by(|a, b| a.language.cmp(&b.language));
+    }
+    let (deck_path, in_place) = deck_path(dir, pictures_path)?;
+    let deck = storage
+        .get_decks()
+        .await?
+        .into_iter()
+        .find(|deck| deck.path == deck_path);
+    let deck_info = deck_files::deck_info(dir)?;
+    let deck_name = match (deck_info.name, &deck) {
+        (Some(name), _) => name,
+        (None, Some(deck)) => deck.name.clone(),
+        (None, None) => dir
+            .canonicalize()?
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+    };
+    let existing_cards = match &deck {
+        Some(deck) => storage.get_deck_cards(deck.id).await?,
+        None => vec![],
+    };
+
+    let mut report = ImportReport {
+        deck_name,
+        deck_path,
+        with_replacement: deck_info.with_replacement,
+        markup: deck_info.markup,
+        dry_run,
+        added: vec![],
+        changed: vec![],
+        unchanged: 0,
+        missing: vec![],
+        changed_images: vec![],
+    };
+    for card in &cards {
+        match existing_cards.iter().find(|existing| existing.filename == card.filename) {
+            None => report.added.push(card.filename.clone()),
+            Some(existing) => {
+                let changed = card.weight != existing.weight
+                    || card
+                        .translations
+                        .iter()
+                        .any(|translation| !existing.translations.contains(translation));
+                if changed {
+                    report.changed.push(card.filename.clone());
+                } else {
+                    report.unchanged += 1;
+                }
+            }
+        }
+    }
+    report.missing = existing_cards
+        .iter()
+        .filter(|existing| cards.iter().all(|card| card.filename != existing.filename))
+        .map(|existing| existing.filename.clone())
+        .collect();
+
+    //images of a deck imported in place are already where the bot reads them
+    if !in_place {
+        let target = Path::new(pictures_path).join(&report.deck_path);
+        for card in &cards {
+            for translation in &card.translations {
+                let source = dir.join(&translation.language).join(&card.filename);
+                let destination = target.join(&translation.language).join(&card.filename);
+                if destination.is_file() && fs::read(&destination)? != fs::read(&source)? {
+                    report
+                        .changed_images
+                        .push((card.filename.clone(), translation.language.clone()));
+                }
+                if !dry_run {
+                    copy_file(&source, &destination)?;
+                }
+            }
+        }
+        if !dry_run {
+            copy_file(&dir.join("data.csv"), &target.join("data.csv"))?;
+            let deck_info = deck_files::DeckInfo {
+                name: Some(report.deck_name.clone()),
+                with_replacement: report.with_replacement,
+                markup: report.markup,
+            };
+            deck_files::write_deck_info(&target, &deck_info)?;
+        }
+    }
+
+    if !dry_run {
+        storage
+            .import_deck(
+                &report.deck_name,
+                &report.deck_path,
+                report.with_replacement,
+                report.markup,
+                &cards,
+                &report.changed_images,
+            )
+            .await?;
+    }
+    Ok(report)
+}
+
+//writes data.csv, deck.yml and the images of the deck with the name, the main deck by default
+pub async fn export(
+    dir: &Path,
+    deck_name: Option<&str>,
+    pictures_path: &str,
+    storage: &dyn Storage,
+) -> Result<ExportReport> {
+    let deck = storage
+        .get_decks()
+        .await?
+        .into_iter()
+        .find(|deck| match deck_name {
+            Some(name) => deck.name == name || deck.path == name,
+            None => deck.path == ".",
+        })
+        .ok_or(anyhow!("No deck {}!", deck_name.unwrap_or(".")))?;
+    let cards = storage.get_deck_cards(deck.id).await?;
+    fs::create_dir_all(dir)?;
+    deck_files::write_cards(dir, &cards)?;
+    let deck_info = deck_files::DeckInfo {
+        name: Some(deck.name.clone()),
+        with_replacement: deck.with_replacement,
+        markup: deck.markup,
+    };
+    deck_files::write_deck_info(dir, &deck_info)?;
+    let mut report = ExportReport {
+        cards: cards.len(),
+        images: 0,
+        missing_images: vec![],
+    };
+    let source: PathBuf = Path::new(pictures_path).join(&deck.path);
+    for card in &cards {
+        for translation in &card.translations {
+            let image = Path::new(&translation.language).join(&card.filename);
+            if source.join(&image).is_file() {
+                copy_file(&source.join(&image), &dir.join(&image))?;
+                report.images += 1;
+            } else {
+                report.missing_images.push(image.to_string_lossy().to_string());
+            }
+        }
+    }
+    Ok(report)
+}
+
+//copying a file onto itself would truncate it
+fn copy_file(source: &Path, destination: &Path) -> Result<()> {
+    if destination.exists() && destination.canonicalize()? == source.canonicalize()? {
+        return Ok(());
+    }
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(source, destination)?;
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use std::{fmt, time::Duration};
+
+use teloxide::{ApiError, RequestError};
+
+//what went wrong, so that handlers tell the user and log it accordingly,
+//errors are passed around as anyhow errors and sorted out by Error::from
+#[derive(Debug)]
+pub enum Error {
+    Database(sqlx::Error),
+    //the user blocked the bot or the chat is not available anymore
+    Blocked(RequestError),
+    //telegram asks to wait before sending more
+    RateLimited(Duration),
+    //the message to edit, delete or answer is gone
+    MessageNotFound(RequestError),
+    //other errors of telegram requests
+    Telegram(RequestError),
+    Config(String),
+    //translations, decks, spreads and settings which don't have what is asked for
+    Data(String),
+    Other(anyhow::Error),
+}
+
+impl Error {
+    //the translation key of the message for the user, none if there is no one to tell
+    pub fn message_key(&self) -> Option<&'static str> {
+        match self {
+            Error::Blocked(_) => None,
+            Error::Database(_) => Some("database_error"),
+            Error::RateLimited(_) => Some("rate_limited"),
+            Error::MessageNotFound(_) => Some("message_not_found"),
+            Error::Data(_) => Some("data_error"),
+            Error::Telegram(_) | Error::Config(_) | Error::Other(_) => Some("unknown_error"),
+        }
+    }
+
+    //problems of the bot are errors, problems of single chats are not
+    pub fn log(&self, context: &str) {
+        match self {
+            Error::Database(_) | Error::Config(_) | Error::Other(_) => tracing::error!("{}: {:?}", context, self),
+            Error::RateLimited(_) | Error::Telegram(_) | Error::Data(_) => tracing::warn!("{}: {:?}", context, self),
+            Error::Blocked(_) | Error::MessageNotFound(_) => tracing::info!("{}: {}", context, self),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Database(e) => write!(f, "Database error: {}", e),
+            Error::Blocked(e) => write!(f, "The chat is not available: {}", e),
+            Error::RateLimited(duration) => write!(f, "Rate limited for {} seconds", duration.as_secs()),
+            Error::MessageNotFound(e) => write!(f, "The message is not found: {}", e),
+            Error::Telegram(e) => write!(f, "Telegram error: {}", e),
+            Error::Config(message) => write!(f, "Config error: {}", message),
+            Error::Data(message) => write!(f, "Data error: {}", message),
+            Error::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<RequestError> for Error {
+    fn from(e: RequestError) -> Error {
+        match e {
+            RequestError::RetryAfter(duration) => Error::RateLimited(duration),
+            RequestError::Api(
+                ApiError::BotBlocked
+                | ApiError::BotKicked
+                | ApiError::BotKickedFromSupergroup
+                | ApiError::UserDeactivated
+                | ApiError::ChatNotFound
+                | ApiError::CantInitiateConversation
+                | ApiError::CantTalkWithBots,
+            ) => Error::Blocked(e),
+            RequestError::Api(
+                ApiError::MessageToEditNotFound
+                | ApiError::MessageToDeleteNotFound
+                | ApiError::MessageToReplyNotFound
+                | ApiError::MessageIdInvalid
+                | ApiError::InvalidQueryId,
+            ) => Error::MessageNotFound(e),
+            e => Error::Telegram(e),
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Error {
+        let e = match e.downcast::<Error>() {
+            Ok(e) => return e,
+            Err(e) => e,
+        };
+        let e = match e.downcast::<sqlx::Error>() {
+            Ok(e) => return Error::Database(e),
+            Err(e) => e,
+        };
+        match e.downcast::<RequestError>() {
+            Ok(e) => Error::from(e),
+            Err(e) => Error::Other(e),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, path::Path};
+
+use crate::process::Language;
+use crate::storage::Storage;
+
+//telegram's id of an uploaded card image, valid only for the bot which has uploaded it
+pub struct FileId {
+    pub card_id: i32,
+    pub language: Language,
+    pub reversed: bool,
+    //of the image file the id was got for
+    pub content_hash: String,
+    //relative to pictures_path
+    pub image_path: String,
+}
+
+pub fn image_path(deck_path: &str, language: &str, filename: &str) -> String {
+    Path::new(deck_path)
+        .join(language)
+        .join(filename)
+        .to_string_lossy()
+        .to_string()
+}
+
+//sha256 of the file, hex encoded
+pub fn content_hash(path: &Path) -> Result<String> {
+    Ok(format!("{:x}", Sha256::digest(std::fs::read(path)?)))
+}
+
+//checks the file ids of every active bot, as images are shared by all bots
+pub async fn check_all_file_ids(pictures_path: &str, storage: &dyn Storage) -> Result<usize> {
+    let mut forgotten = 0;
+    for bot in storage.get_active_bots().await? {
+        forgotten += check_file_ids(pictures_path, &*storage.with_bot(bot.id)).await?;
+    }
+    Ok(forgotten)
+}
+
+//forgets the file ids of the images which have been changed or removed since they were uploaded,
+//and the ids without hashes, as nothing tells which image they are of,
+//returns the number of the forgotten ids
+pub async fn check_file_ids(pictures_path: &str, storage: &dyn Storage) -> Result<usize> {
+    let mut hashes: HashMap<String, Option<String>> = HashMap::new();
+    let mut forgotten = 0;
+    for file_id in storage.get_file_ids().await? {
+        let hash = hashes
+            .entry(file_id.image_path.clone())
+            .or_insert_with(|| content_hash(&Path::new(pictures_path).join(&file_id.image_path)).ok())
+            .clone();
+        if hash.as_deref() != Some(file_id.content_hash.as_str()) {
+            storage
+                .delete_file_id(file_id.card_id, &file_id.language, file_id.reversed)
+                .await?;
+            forgotten += 1;
+        }
+    }
+    Ok(forgotten)
+}
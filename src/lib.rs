@@ -1,44 +1,209 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
 use anyhow::{anyhow, Result};
-use teloxide::prelude::*;
+use teloxide::{
+    prelude::*,
+    update_listeners::{self, webhooks, UpdateListener},
+};
 
+pub mod config;
+mod admin;
+mod callbacks;
+mod check;
 mod database_interactions;
+mod deck_files;
+mod deck_import;
+mod error;
+mod file_ids;
+mod markup;
+mod memory_storage;
+mod postgres_storage;
 mod process;
+mod reloadable;
+mod scheduler;
+mod spreads;
+mod sqlite_storage;
+mod storage;
 mod telegram_interactions;
 mod translations;
+mod warmup;
+#[cfg(test)]
+mod tests;
 
-pub async fn start(config_file_name: String) -> Result<()> {
-    let config_file_string = std::fs::read_to_string(config_file_name)?;
-    let json: serde_json::Value = serde_json::from_str(&config_file_string)?;
-    let database_path = json
-        .get("database_path")
-        .ok_or(anyhow!("No database_path in json file!"))?
-        .as_str()
-        .ok_or(anyhow!("Error getting database_path!"))?;
-    let pool = database_interactions::pool(database_path.to_string()).await?;
-    database_interactions::check_cards_table(&pool).await?;
-    let bot = Bot::new(
-        json.get("bot_token")
-        .ok_or(anyhow!("No bot_token in json file!"))?
-        .as_str()
-        .ok_or(anyhow!("Error getting bot_token!"))?
-    );
-
-    let translation = translations::translation()?;
+use config::Config;
+use reloadable::Reloadable;
+
+//imports the deck folder and prints what has changed,
+//with dry_run only validates the folder and prints what would change
+pub async fn import_deck(config: &Config, dir: &str, dry_run: bool) -> Result<()> {
+    let storage = storage::storage(config).await?;
+    let report = deck_import::import(Path::new(dir), &config.pictures_path, &*storage, dry_run).await?;
+    println!("{}", report);
+    Ok(())
+}
+
+//exports the deck with the name, the main deck by default, to the folder
+pub async fn export_deck(config: &Config, dir: &str, deck_name: Option<&str>) -> Result<()> {
+    let storage = storage::storage(config).await?;
+    let report = deck_import::export(Path::new(dir), deck_name, &config.pictures_path, &*storage).await?;
+    println!("{}", report);
+    Ok(())
+}
+
+//uploads every card image without a file id to the storage chat with every active bot
+//and prints the reports
+pub async fn warmup_images(config: &Config) -> Result<()> {
+    let storage_chat_id = warmup::storage_chat_id(config.storage_chat_id)?;
+    let storage = storage::storage(config).await?;
+    storage.check_cards_table(&config.pictures_path).await?;
+    file_ids::check_all_file_ids(&config.pictures_path, &*storage).await?;
+    let languages: Vec<_> = translations::translation(&config.pictures_path, &config.default_language)?
+        .languages()
+        .collect();
+    for bot_info in storage.get_active_bots().await? {
+        let storage = storage.with_bot(bot_info.id);
+        let (images, mut report) = warmup::pending_images(&languages, &config.pictures_path, &*storage).await?;
+        println!("Uploading {} images with {} to the storage chat...", images.len(), bot_info.name);
+        let bot = Bot::new(bot_info.token(config));
+        warmup::upload(images, storage_chat_id, &bot, &config.pictures_path, &*storage, &mut report).await?;
+        println!("{}", report);
+    }
+    Ok(())
+}
+
+//checks translations and deck images like the start does and prints the report
+pub async fn check_assets(config: &Config) -> Result<()> {
+    let storage = storage::storage(config).await?;
+    storage.check_cards_table(&config.pictures_path).await?;
+    let translation = translations::translation(&config.pictures_path, &config.default_language)?;
+    let report = check::check(&translation, &config.pictures_path, &*storage).await?;
+    println!("{}", report);
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(anyhow!("Translations or images have problems!"))
+    }
+}
+
+//one dispatcher and one scheduler are started for every active bot in the bots table,
+//translations, spreads and decks are shared by them
+pub async fn start(config: Config) -> Result<()> {
+    let storage = storage::storage(&config).await?;
+    storage.check_cards_table(&config.pictures_path).await?;
+    let forgotten = file_ids::check_all_file_ids(&config.pictures_path, &*storage).await?;
+    if forgotten > 0 {
+        tracing::info!("Forgot {} file ids of changed images", forgotten);
+    }
+    let bots = storage.get_active_bots().await?;
+    if bots.is_empty() {
+        return Err(anyhow!("No active bots in the bots table!"));
+    }
+
+    let translation = translations::translation(&config.pictures_path, &config.default_language)?;
+    check::check(&translation, &config.pictures_path, &*storage)
+        .await?
+        .apply(config.startup_check)?;
+    let translation = Arc::new(Reloadable::new(translation));
+    let spreads = Arc::new(Reloadable::new(spreads::spreads(&config.pictures_path)?));
+    let config = Arc::new(config);
+
+    let mut dispatchers = vec![];
+    //with a webhook all bots share one server, every bot has its own path
+    let mut router = axum::Router::new();
+    let mut stop_tokens = vec![];
+    let mut stop_flags = vec![];
+    for bot_info in bots {
+        tracing::info!("Starting bot {} ({})", bot_info.name, bot_info.id);
+        let bot = Bot::new(bot_info.token(&config));
+        let storage = storage.with_bot(bot_info.id);
+        let dispatcher = match &config.webhook {
+            Some(webhook) => {
+                let (mut listener, stop_flag, bot_router) =
+                    webhooks::axum_to_router(bot.clone(), webhook.options(bot_info.id)?).await?;
+                router = router.merge(bot_router);
+                stop_tokens.push(listener.stop_token());
+                //deletes the webhook when the bot stops
+                stop_flags.push(tokio::spawn(stop_flag));
+                tokio::spawn(run_bot(bot, listener, storage, translation.clone(), spreads.clone(), config.clone()))
+            }
+            None => {
+                let listener = update_listeners::polling_default(bot.clone()).await;
+                tokio::spawn(run_bot(bot, listener, storage, translation.clone(), spreads.clone(), config.clone()))
+            }
+        };
+        dispatchers.push(dispatcher);
+    }
+    let server = match &config.webhook {
+        Some(webhook) => {
+            //the server stops when every bot has stopped and deleted its webhook
+            let shutdown = async move {
+                for stop_flag in stop_flags {
+                    let _ = stop_flag.await;
+                }
+            };
+            let server = axum::Server::try_bind(&webhook.address)?
+                .serve(router.into_make_service())
+                .with_graceful_shutdown(shutdown);
+            Some(tokio::spawn(async move {
+                if let Err(e) = server.await {
+                    tracing::error!("Webhook server error: {}", e);
+                    for stop_token in stop_tokens {
+                        stop_token.stop();
+                    }
+                }
+            }))
+        }
+        None => None,
+    };
+    for dispatcher in dispatchers {
+        dispatcher.await??;
+    }
+    if let Some(server) = server {
+        server.await?;
+    }
+    Ok(())
+}
+
+async fn run_bot<L>(
+    bot: Bot,
+    listener: L,
+    storage: Arc<dyn storage::Storage>,
+    translation: Arc<Reloadable<translations::Translation>>,
+    spreads: Arc<Reloadable<spreads::Spreads>>,
+    config: Arc<Config>,
+) -> Result<()>
+where
+    L: UpdateListener + Send,
+    L::Err: std::fmt::Debug,
+{
+    tokio::spawn(scheduler::run(
+        bot.clone(),
+        storage.clone(),
+        translation.clone(),
+        spreads.clone(),
+        config.clone(),
+    ));
 
     let handler = dptree::entry()
         .branch(Update::filter_message()
         .endpoint(telegram_interactions::message_handler))
         .branch(Update::filter_callback_query()
-        .endpoint(telegram_interactions::callback_handler));
+        .endpoint(telegram_interactions::callback_handler))
+        .branch(Update::filter_inline_query()
+        .endpoint(telegram_interactions::inline_query_handler));
 
-    Dispatcher::builder(bot, handler)
-        .dependencies(dptree::deps![pool, Arc::new(translation)])
+    let mut dispatcher = Dispatcher::builder(bot.clone(), handler)
+        .dependencies(dptree::deps![storage, translation, spreads, config.clone()])
         .enable_ctrlc_handler()
-        .build()
-        .dispatch()
+        .build();
+
+    //the listener is long polling, unless webhook is configured
+    dispatcher
+        .dispatch_with_listener(
+            listener,
+            LoggingErrorHandler::with_custom_text("An error from the update listener"),
+        )
         .await;
 
     Ok(())
-}
\ No newline at end of file
+}
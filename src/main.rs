@@ -1,23 +1,45 @@
 use anyhow::{anyhow, Result};
 use std::env;
-use telegram_bot_deck_of_cards::start;
+use telegram_bot_deck_of_cards::{check_assets, config::Config, export_deck, import_deck, start, warmup_images};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    println!("Hello, bot!");
+    let mut args = env::args().skip(1);
+    let config_file_name = match args.next() {
+        Some(arg) => arg,
+        None => return Err(anyhow!("Didn't get config path param")),
+    };
+    let subcommand = args.next();
+    let config = Config::load(&config_file_name)?;
+    //subcommands log too, e.g. the images warmup uploads
     let subscriber = tracing_subscriber::fmt()
         .with_file(true)
         .with_line_number(true)
         .with_target(false)
+        .with_max_level(config.log_level.parse::<tracing::Level>()?)
         .finish();
     tracing::subscriber::set_global_default(subscriber)?;
+    //subcommands work with the database and exit, without starting the bot
+    match subcommand.as_deref() {
+        Some("--print-config") => {
+            println!("{}", serde_json::to_string_pretty(&config.redacted())?);
+            return Ok(());
+        }
+        Some(command @ ("import" | "validate")) => {
+            let dir = args.next().ok_or(anyhow!("Usage: {} <dir>", command))?;
+            return import_deck(&config, &dir, command == "validate").await;
+        }
+        Some("export") => {
+            let dir = args.next().ok_or(anyhow!("Usage: export <dir> [deck name]"))?;
+            return export_deck(&config, &dir, args.next().as_deref()).await;
+        }
+        Some("warmup") => return warmup_images(&config).await,
+        Some("check") => return check_assets(&config).await,
+        Some(other) => return Err(anyhow!("Unknown param {}", other)),
+        None => {}
+    }
 
-    let mut args = env::args();
-    let config_file_name = match args.nth(1) {
-        Some(arg) => arg,
-        None => return Err(anyhow!("Didn't get config path param")),
-    };
-
-    start(config_file_name).await?;
+    println!("Hello, bot!");
+    start(config).await?;
     Ok(())
 }
@@ -0,0 +1,168 @@
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+
+//card texts are sent with the html parse mode,
+//telegram limits captions by the length of the visible text
+pub const CAPTION_LIMIT: usize = 1024;
+//links can lead only to these
+const LINK_SCHEMES: [&str; 3] = ["https://", "http://", "tg://"];
+
+//how deck descriptions and translation texts are written,
+//plain texts are sent as they are, markdown is a safe subset:
+//**bold**, _italic_, [text](https://link), line breaks and \ before a literal *, _, [ or \,
+//{placeholders} of translations are kept as they are
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Plain,
+    Markdown,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Format> {
+        match s {
+            "plain" => Ok(Format::Plain),
+            "markdown" => Ok(Format::Markdown),
+            _ => Err(anyhow!("Unknown markup format {}!", s)),
+        }
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn bold(text: &str) -> String {
+    format!("<b>{}</b>", escape_html(text))
+}
+
+//telegram html of the text written in the format, malformed markdown is an error
+pub fn render(text: &str, format: Format) -> Result<String> {
+    match format {
+        Format::Plain => Ok(escape_html(text)),
+        Format::Markdown => markdown_to_html(text),
+    }
+}
+
+fn markdown_to_html(text: &str) -> Result<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut html = String::new();
+    //tags which are open now, innermost last
+    let mut open: Vec<&str> = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let tag = match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                html.push_str(&escape_html(&chars[i + 1].to_string()));
+                i += 2;
+                continue;
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                i += 2;
+                "b"
+            }
+            //an underscore inside a word, like in snake_case, is not markup
+            '_' if i > 0
+                && chars[i - 1].is_alphanumeric()
+                && chars.get(i + 1).is_some_and(|c| c.is_alphanumeric()) =>
+            {
+                html.push('_');
+                i += 1;
+                continue;
+            }
+            '_' => {
+                i += 1;
+                "i"
+            }
+            //translation placeholders are not markup
+            '{' => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|c| *c == '}')
+                    .ok_or(anyhow!("Unclosed {{ at character {}!", i))?;
+                let placeholder: String = chars[i..=i + end].iter().collect();
+                html.push_str(&escape_html(&placeholder));
+                i += end + 1;
+                continue;
+            }
+            '[' => {
+                let (link, end) = link_to_html(&chars, i)?;
+                html.push_str(&link);
+                i = end;
+                continue;
+            }
+            c => {
+                html.push_str(&escape_html(&c.to_string()));
+                i += 1;
+                continue;
+            }
+        };
+        if open.last() == Some(&tag) {
+            open.pop();
+            html.push_str(&format!("</{}>", tag));
+        } else if open.contains(&tag) {
+            return Err(anyhow!("Overlapping ** and _ at character {}!", i));
+        } else {
+            open.push(tag);
+            html.push_str(&format!("<{}>", tag));
+        }
+    }
+    match open.last() {
+        Some(&"b") => Err(anyhow!("Unclosed **!")),
+        Some(_) => Err(anyhow!("Unclosed _!")),
+        None => Ok(html),
+    }
+}
+
+//[text](url) starting at the index of [, returns the html and the index after it
+fn link_to_html(chars: &[char], start: usize) -> Result<(String, usize)> {
+    let rest: String = chars[start + 1..].iter().collect();
+    let (text, rest) = rest
+        .split_once("](")
+        .ok_or(anyhow!("Unclosed [ at character {}!", start))?;
+    let (url, _) = rest
+        .split_once(')')
+        .ok_or(anyhow!("Unclosed link at character {}!", start))?;
+    if text.is_empty() || text.contains('[') {
+        return Err(anyhow!("Wrong link text at character {}!", start));
+    }
+    if !LINK_SCHEMES.iter().any(|scheme| url.starts_with(scheme)) || url.contains(char::is_whitespace) {
+        return Err(anyhow!("Wrong link {}!", url));
+    }
+    let html = format!("<a href=\"{}\">{}</a>", escape_html(url), markdown_to_html(text)?);
+    Ok((html, start + text.chars().count() + url.chars().count() + 4))
+}
+
+//the length of the html as telegram shows it, in utf-16 code units like telegram counts,
+//tags are not shown and every entity is one character
+pub fn visible_length(html: &str) -> usize {
+    let mut length = 0;
+    let mut in_tag = false;
+    let mut in_entity = false;
+    for c in html.chars() {
+        match c {
+            '<' if !in_entity => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if in_tag => {}
+            '&' => {
+                in_entity = true;
+                length += 1;
+            }
+            ';' if in_entity => in_entity = false,
+            _ if in_entity => {}
+            _ => length += c.len_utf16(),
+        }
+    }
+    length
+}
+
+pub fn fits_caption(html: &str) -> bool {
+    visible_length(html) <= CAPTION_LIMIT
+}
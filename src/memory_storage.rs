@@ -0,0 +1,828 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use time::{Date, OffsetDateTime, Time};
+
+use crate::admin::{ChatState, Stats};
+use crate::deck_files;
+use crate::file_ids::{self, FileId};
+use crate::markup;
+use crate::process::{self, Card, CardData, DailyCardSubscriber, Deck, Draw, Language};
+use crate::storage::{BotInfo, Storage, CONFIG_TOKEN_KEY};
+
+//the only active bot, the same as the one created by migrations, it uses bot_token from the config,
+//bound storages of other bots still have their own subscribers and file ids
+const ACTIVE_BOT_ID: i32 = 1;
+//the main deck in pictures_path itself, the same as the one created by migrations
+const MAIN_DECK_ID: i32 = 1;
+
+//keeps everything in memory, used for tests and for trying the bot out without a database
+pub struct MemoryStorage {
+    state: Arc<Mutex<State>>,
+    bot_id: Option<i32>,
+}
+
+#[derive(Default)]
+struct State {
+    subscribers: Vec<Subscriber>,
+    decks: Vec<Deck>,
+    cards: Vec<MemoryCard>,
+    draws: Vec<MemoryDraw>,
+    file_ids: FileIds,
+}
+
+//(bot id, card id, language, reversed) -> (file id, content hash)
+type FileIds = HashMap<(i32, i32, String, bool), (String, String)>;
+
+struct Subscriber {
+    id: i32,
+    bot_id: i32,
+    chat_id: String,
+    //members of group chats have their own rows
+    user_id: Option<i64>,
+    created_at: OffsetDateTime,
+    drawn_cards: Vec<i32>,
+    language: Language,
+    descriptions_format: i32,
+    deck_id: i32,
+    reversed_cards: bool,
+    daily_card_time: Option<Time>,
+    daily_card_utc_offset: i32,
+    daily_card_sent_on: Option<Date>,
+    per_member_decks: bool,
+}
+
+struct MemoryCard {
+    id: i32,
+    deck_id: i32,
+    filename: String,
+    weight: i32,
+    translations: HashMap<String, MemoryCardTranslation>,
+}
+
+struct MemoryCardTranslation {
+    name: String,
+    description: String,
+    reversed_name: Option<String>,
+    reversed_description: Option<String>,
+}
+
+struct MemoryDraw {
+    id: i32,
+    subscriber_id: i32,
+    card_id: i32,
+    deck_id: i32,
+    reversed: bool,
+    drawn_at: OffsetDateTime,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        let state = State {
+            decks: vec![Deck {
+                id: MAIN_DECK_ID,
+                name: "Main".to_string(),
+                path: ".".to_string(),
+                with_replacement: false,
+                markup: markup::Format::Plain,
+            }],
+            ..Default::default()
+        };
+        MemoryStorage {
+            state: Arc::new(Mutex::new(state)),
+            bot_id: None,
+        }
+    }
+
+    //a panic in another handler doesn't make the data unusable
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    fn subscriber(&self, subscriber_id: i32) -> Result<&Subscriber> {
+        self.subscribers
+            .iter()
+            .find(|subscriber| subscriber.id == subscriber_id)
+            .ok_or(anyhow!("No subscriber {}!", subscriber_id))
+    }
+
+    fn subscriber_mut(&mut self, subscriber_id: i32) -> Result<&mut Subscriber> {
+        self.subscribers
+            .iter_mut()
+            .find(|subscriber| subscriber.id == subscriber_id)
+            .ok_or(anyhow!("No subscriber {}!", subscriber_id))
+    }
+
+    //the rows of a chat and of all its members
+    fn chat_subscribers_mut(&mut self, subscriber_id: i32) -> Result<impl Iterator<Item = &mut Subscriber>> {
+        let chat = self.subscriber(subscriber_id)?;
+        let (bot_id, chat_id) = (chat.bot_id, chat.chat_id.clone());
+        Ok(self
+            .subscribers
+            .iter_mut()
+            .filter(move |subscriber| subscriber.bot_id == bot_id && subscriber.chat_id == chat_id))
+    }
+
+    //the rows of the bot's chats and of all their members
+    fn bot_subscribers(&self, bot_id: i32) -> impl Iterator<Item = &Subscriber> {
+        self.subscribers.iter().filter(move |subscriber| subscriber.bot_id == bot_id)
+    }
+
+    fn chats(&self, bot_id: i32) -> impl Iterator<Item = &Subscriber> {
+        self.bot_subscribers(bot_id).filter(|subscriber| subscriber.user_id.is_none())
+    }
+
+    fn deck(&self, deck_id: i32) -> Result<&Deck> {
+        self.decks
+            .iter()
+            .find(|deck| deck.id == deck_id)
+            .ok_or(anyhow!("No deck {}!", deck_id))
+    }
+
+    fn card(&self, card_id: i32, language: &Language, bot_id: i32) -> Option<Card> {
+        self.cards
+            .iter()
+            .find(|card| card.id == card_id)
+            .and_then(|card| card.translated(language, &self.file_ids, bot_id))
+    }
+}
+
+impl MemoryCard {
+    fn translated(&self, language: &Language, file_ids: &FileIds, bot_id: i32) -> Option<Card> {
+        let translation = self.translations.get(language.code())?;
+        let file_id = |reversed: bool| {
+            file_ids
+                .get(&(bot_id, self.id, language.code().to_string(), reversed))
+                .map(|(file_id, _)| file_id.clone())
+        };
+        Some(Card {
+            id: self.id,
+            filename: self.filename.clone(),
+            name: translation.name.clone(),
+            description: translation.description.clone(),
+            telegram_file_id: file_id(false),
+            reversed_name: translation.reversed_name.clone(),
+            reversed_description: translation.reversed_description.clone(),
+            reversed_telegram_file_id: file_id(true),
+            weight: self.weight,
+        })
+    }
+}
+
+fn today() -> OffsetDateTime {
+    OffsetDateTime::now_utc().replace_time(Time::MIDNIGHT)
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    fn bot_id(&self) -> Result<i32> {
+        self.bot_id.ok_or(anyhow!("The storage is not bound to a bot!"))
+    }
+
+    fn with_bot(&self, bot_id: i32) -> Arc<dyn Storage> {
+        Arc::new(MemoryStorage {
+            state: self.state.clone(),
+            bot_id: Some(bot_id),
+        })
+    }
+
+    async fn get_active_bots(&self) -> Result<Vec<BotInfo>> {
+        Ok(vec![BotInfo {
+            id: ACTIVE_BOT_ID,
+            name: "Main".to_string(),
+            key: CONFIG_TOKEN_KEY.to_string(),
+        }])
+    }
+
+    async fn get_subscriber_id(&self, chat_id: String) -> Result<Option<i32>> {
+        Ok(self
+            .state()
+            .chats(self.bot_id()?)
+            .find(|subscriber| subscriber.chat_id == chat_id)
+            .map(|subscriber| subscriber.id))
+    }
+
+    async fn create_subscriber(&self, chat_id: String, language: &Language) -> Result<i32> {
+        let bot_id = self.bot_id()?;
+        let mut state = self.state();
+        if state.chats(bot_id).any(|subscriber| subscriber.chat_id == chat_id) {
+            return Err(anyhow!("Subscriber {} already exists!", chat_id));
+        }
+        let id = state.subscribers.len() as i32 + 1;
+        state.subscribers.push(Subscriber {
+            id,
+            bot_id,
+            chat_id,
+            user_id: None,
+            created_at: OffsetDateTime::now_utc(),
+            drawn_cards: vec![],
+            language: language.clone(),
+            descriptions_format: process::FULL_DESCRIPTIONS,
+            deck_id: MAIN_DECK_ID,
+            reversed_cards: false,
+            daily_card_time: None,
+            daily_card_utc_offset: 0,
+            daily_card_sent_on: None,
+            per_member_decks: false,
+        });
+        Ok(id)
+    }
+
+    async fn get_member_id(&self, chat_id: String, user_id: i64) -> Result<Option<i32>> {
+        Ok(self
+            .state()
+            .bot_subscribers(self.bot_id()?)
+            .find(|subscriber| subscriber.chat_id == chat_id && subscriber.user_id == Some(user_id))
+            .map(|subscriber| subscriber.id))
+    }
+
+    async fn create_member(&self, chat_id: String, user_id: i64, subscriber_id: i32) -> Result<i32> {
+        let bot_id = self.bot_id()?;
+        let mut state = self.state();
+        if state
+            .bot_subscribers(bot_id)
+            .any(|subscriber| subscriber.chat_id == chat_id && subscriber.user_id == Some(user_id))
+        {
+            return Err(anyhow!("Member {} of {} already exists!", user_id, chat_id));
+        }
+        let chat = state.subscriber(subscriber_id)?;
+        let member = Subscriber {
+            id: state.subscribers.len() as i32 + 1,
+            bot_id,
+            chat_id,
+            user_id: Some(user_id),
+            created_at: OffsetDateTime::now_utc(),
+            drawn_cards: vec![],
+            language: chat.language.clone(),
+            descriptions_format: chat.descriptions_format,
+            deck_id: chat.deck_id,
+            reversed_cards: chat.reversed_cards,
+            daily_card_time: None,
+            daily_card_utc_offset: 0,
+            daily_card_sent_on: None,
+            per_member_decks: false,
+        };
+        let id = member.id;
+        state.subscribers.push(member);
+        Ok(id)
+    }
+
+    async fn get_per_member_decks(&self, subscriber_id: i32) -> Result<bool> {
+        Ok(self.state().subscriber(subscriber_id)?.per_member_decks)
+    }
+
+    async fn set_per_member_decks(&self, value: bool, subscriber_id: i32) -> Result<()> {
+        self.state().subscriber_mut(subscriber_id)?.per_member_decks = value;
+        Ok(())
+    }
+
+    async fn get_language(&self, subscriber_id: i32) -> Result<Language> {
+        Ok(self.state().subscriber(subscriber_id)?.language.clone())
+    }
+
+    async fn set_language(&self, language: &Language, subscriber_id: i32) -> Result<()> {
+        self.state().subscriber_mut(subscriber_id)?.language = language.clone();
+        Ok(())
+    }
+
+    async fn get_descriptions_format(&self, subscriber_id: i32) -> Result<i32> {
+        Ok(self.state().subscriber(subscriber_id)?.descriptions_format)
+    }
+
+    async fn set_description(&self, value: i32, subscriber_id: i32) -> Result<()> {
+        self.state().subscriber_mut(subscriber_id)?.descriptions_format = value;
+        Ok(())
+    }
+
+    async fn set_reversed_cards(&self, value: bool, subscriber_id: i32) -> Result<()> {
+        for subscriber in self.state().chat_subscribers_mut(subscriber_id)? {
+            subscriber.reversed_cards = value;
+        }
+        Ok(())
+    }
+
+    async fn get_daily_card(&self, subscriber_id: i32) -> Result<(Option<Time>, i32)> {
+        let state = self.state();
+        let subscriber = state.subscriber(subscriber_id)?;
+        Ok((subscriber.daily_card_time, subscriber.daily_card_utc_offset))
+    }
+
+    async fn set_daily_card(&self, time: Option<Time>, utc_offset: i32, subscriber_id: i32) -> Result<()> {
+        let mut state = self.state();
+        let subscriber = state.subscriber_mut(subscriber_id)?;
+        subscriber.daily_card_time = time;
+        subscriber.daily_card_utc_offset = utc_offset;
+        Ok(())
+    }
+
+    async fn get_daily_card_subscribers(&self) -> Result<Vec<DailyCardSubscriber>> {
+        Ok(self
+            .state()
+            .bot_subscribers(self.bot_id()?)
+            .filter_map(|subscriber| {
+                Some(DailyCardSubscriber {
+                    subscriber_id: subscriber.id,
+                    chat_id: subscriber.chat_id.clone(),
+                    daily_card_time: subscriber.daily_card_time?,
+                    daily_card_utc_offset: subscriber.daily_card_utc_offset,
+                    daily_card_sent_on: subscriber.daily_card_sent_on,
+                })
+            })
+            .collect())
+    }
+
+    async fn mark_daily_card_sent(&self, subscriber_id: i32, date: Date) -> Result<bool> {
+        let mut state = self.state();
+        let subscriber = state.subscriber_mut(subscriber_id)?;
+        if subscriber.daily_card_sent_on.is_none_or(|sent_on| sent_on < date) {
+            subscriber.daily_card_sent_on = Some(date);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    async fn get_decks(&self) -> Result<Vec<Deck>> {
+        Ok(self.state().decks.clone())
+    }
+
+    async fn get_deck(&self, subscriber_id: i32) -> Result<Deck> {
+        let state = self.state();
+        let deck_id = state.subscriber(subscriber_id)?.deck_id;
+        Ok(state.deck(deck_id)?.clone())
+    }
+
+    async fn set_deck(&self, deck_id: i32, subscriber_id: i32) -> Result<()> {
+        let mut state = self.state();
+        state.deck(deck_id)?;
+        for subscriber in state.chat_subscribers_mut(subscriber_id)? {
+            subscriber.deck_id = deck_id;
+        }
+        Ok(())
+    }
+
+    async fn check_cards_table(&self, pictures_path: &str) -> Result<()> {
+        //files are read before locking the state,
+        //the drawing of every deck follows its deck.yml
+        let deck_folders = deck_files::deck_folders(pictures_path)?;
+        let main_deck = deck_files::deck_info(Path::new(pictures_path))?;
+        let mut decks = {
+            let mut state = self.state();
+            for deck_folder in deck_folders {
+                match state.decks.iter_mut().find(|deck| deck.path == deck_folder.path) {
+                    Some(deck) => {
+                        deck.with_replacement = deck_folder.with_replacement;
+                        deck.markup = deck_folder.markup;
+                    }
+                    None => {
+                        let id = state.decks.len() as i32 + 1;
+                        state.decks.push(Deck {
+                            id,
+                            name: deck_folder.name,
+                            path: deck_folder.path,
+                            with_replacement: deck_folder.with_replacement,
+                            markup: deck_folder.markup,
+                        });
+                    }
+                }
+            }
+            if let Some(deck) = state.decks.iter_mut().find(|deck| deck.id == MAIN_DECK_ID) {
+                deck.with_replacement = main_deck.with_replacement;
+                deck.markup = main_deck.markup;
+            }
+            state.decks.clone()
+        };
+        decks.retain(|deck| self.state().cards.iter().all(|card| card.deck_id != deck.id));
+        for deck in decks {
+            let cards = deck_files::read_cards(pictures_path, &deck.path)?;
+            let mut state = self.state();
+            for card in cards {
+                let id = state.cards.len() as i32 + 1;
+                let translations = card
+                    .translations
+                    .into_iter()
+                    .map(|translation| {
+                        (
+                            translation.language,
+                            MemoryCardTranslation {
+                                name: translation.name,
+                                description: translation.description,
+                                reversed_name: translation.reversed_name,
+                                reversed_description: translation.reversed_description,
+                            },
+                        )
+                    })
+                    .collect();
+                state.cards.push(MemoryCard {
+                    id,
+                    deck_id: deck.id,
+                    filename: card.filename,
+                    weight: card.weight,
+                    translations,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_deck_cards(&self, deck_id: i32) -> Result<Vec<deck_files::CardRecord>> {
+        Ok(self
+            .state()
+            .cards
+            .iter()
+            .filter(|card| card.deck_id == deck_id)
+            .map(|card| {
+                let mut translations: Vec<deck_files::CardTranslation> = card
+                    .translations
+                    .iter()
+                    .map(|(language, translation)| deck_files::CardTranslation {
+                        language: language.clone(),
+                        name: translation.name.clone(),
+                        description: translation.description.clone(),
+                        reversed_name: translation.reversed_name.clone(),
+                        reversed_description: translation.reversed_description.clone(),
+                    })
+                    .collect();
+                translations.sort_by(|a, b| a.language.cmp(&b.language));
+                deck_files::CardRecord {
+                    filename: card.filename.clone(),
+                    weight: card.weight,
+                    translations,
+                }
+            })
+            .collect())
+    }
+
+    async fn import_deck(
+        &self,
+        name: &str,
+        path: &str,
+        with_replacement: bool,
+        markup: markup::Format,
+        cards: &[deck_files::CardRecord],
+        changed_images: &[(String, String)],
+    ) -> Result<i32> {
+        let mut state = self.state();
+        let deck_id = match state.decks.iter_mut().find(|deck| deck.path == path) {
+            Some(deck) => {
+                deck.name = name.to_string();
+                deck.with_replacement = with_replacement;
+                deck.markup = markup;
+                deck.id
+            }
+            None => {
+                let id = state.decks.len() as i32 + 1;
+                state.decks.push(Deck {
+                    id,
+                    name: name.to_string(),
+                    path: path.to_string(),
+                    with_replacement,
+                    markup,
+                });
+                id
+            }
+        };
+        for card in cards {
+            let index = match state
+                .cards
+                .iter()
+                .position(|c| c.deck_id == deck_id && c.filename == card.filename)
+            {
+                Some(index) => index,
+                None => {
+                    let id = state.cards.len() as i32 + 1;
+                    state.cards.push(MemoryCard {
+                        id,
+                        deck_id,
+                        filename: card.filename.clone(),
+                        weight: card.weight,
+                        translations: HashMap::new(),
+                    });
+                    state.cards.len() - 1
+                }
+            };
+            state.cards[index].weight = card.weight;
+            for translation in &card.translations {
+                let memory_translation = state.cards[index]
+                    .translations
+                    .entry(translation.language.clone())
+                    .or_insert_with(|| MemoryCardTranslation {
+                        name: String::new(),
+                        description: String::new(),
+                        reversed_name: None,
+                        reversed_description: None,
+                    });
+                memory_translation.name = translation.name.clone();
+                memory_translation.description = translation.description.clone();
+                memory_translation.reversed_name = translation.reversed_name.clone();
+                memory_translation.reversed_description = translation.reversed_description.clone();
+            }
+        }
+        for (filename, language) in changed_images {
+            let card_id = state
+                .cards
+                .iter()
+                .find(|card| card.deck_id == deck_id && &card.filename == filename)
+                .map(|card| card.id);
+            if let Some(card_id) = card_id {
+                state
+                    .file_ids
+                    .retain(|(_, id, file_language, _), _| *id != card_id || file_language != language);
+            }
+        }
+        Ok(deck_id)
+    }
+
+    async fn draw_cards(
+        &self,
+        count: usize,
+        _spread_id: Option<&str>,
+        language: &Language,
+        subscriber_id: i32,
+    ) -> Result<Option<(Deck, Vec<(Card, bool)>)>> {
+        let bot_id = self.bot_id()?;
+        let mut state = self.state();
+        let subscriber = state.subscriber(subscriber_id)?;
+        let deck = state.deck(subscriber.deck_id)?.clone();
+        let reversed_cards = subscriber.reversed_cards;
+        let cards: Vec<Card> = state
+            .cards
+            .iter()
+            .filter(|card| card.deck_id == deck.id)
+            .filter(|card| deck.with_replacement || !subscriber.drawn_cards.contains(&card.id))
+            .filter_map(|card| card.translated(language, &state.file_ids, bot_id))
+            .collect();
+        let chosen_cards = match process::choose_cards(cards, count) {
+            Some(chosen_cards) => chosen_cards,
+            None => return Ok(None),
+        };
+
+        //decks with replacement don't put the drawn cards aside
+        if !deck.with_replacement {
+            let subscriber = state.subscriber_mut(subscriber_id)?;
+            subscriber.drawn_cards.extend(chosen_cards.iter().map(|card| card.id));
+        }
+        let chosen_cards: Vec<(Card, bool)> = chosen_cards
+            .into_iter()
+            .map(|card| {
+                let reversed = reversed_cards && rand::random::<bool>();
+                (card, reversed)
+            })
+            .collect();
+        let drawn_at = OffsetDateTime::now_utc();
+        for (card, reversed) in &chosen_cards {
+            let id = state.draws.len() as i32 + 1;
+            state.draws.push(MemoryDraw {
+                id,
+                subscriber_id,
+                card_id: card.id,
+                deck_id: deck.id,
+                reversed: *reversed,
+                drawn_at,
+            });
+        }
+        Ok(Some((deck, chosen_cards)))
+    }
+
+    async fn shuffle_cards_back(&self, subscriber_id: i32) -> Result<()> {
+        let mut state = self.state();
+        let deck_id = state.subscriber(subscriber_id)?.deck_id;
+        let deck_cards: Vec<i32> = state
+            .cards
+            .iter()
+            .filter(|card| card.deck_id == deck_id)
+            .map(|card| card.id)
+            .collect();
+        state
+            .subscriber_mut(subscriber_id)?
+            .drawn_cards
+            .retain(|card_id| !deck_cards.contains(card_id));
+        Ok(())
+    }
+
+    async fn set_file_id(
+        &self,
+        card_id: i32,
+        language: &Language,
+        reversed: bool,
+        id: String,
+        content_hash: String,
+    ) -> Result<()> {
+        let bot_id = self.bot_id()?;
+        self.state()
+            .file_ids
+            .insert((bot_id, card_id, language.code().to_string(), reversed), (id, content_hash));
+        Ok(())
+    }
+
+    async fn get_file_ids(&self) -> Result<Vec<FileId>> {
+        let bot_id = self.bot_id()?;
+        let state = self.state();
+        let mut file_ids = vec![];
+        for ((file_bot_id, card_id, language, reversed), (_, content_hash)) in &state.file_ids {
+            if *file_bot_id != bot_id {
+                continue;
+            }
+            let card = match state.cards.iter().find(|card| card.id == *card_id) {
+                Some(card) => card,
+                None => continue,
+            };
+            file_ids.push(FileId {
+                card_id: *card_id,
+                language: Language::new(language),
+                reversed: *reversed,
+                content_hash: content_hash.clone(),
+                image_path: file_ids::image_path(&state.deck(card.deck_id)?.path, language, &card.filename),
+            });
+        }
+        file_ids.sort_by_key(|file_id| file_id.card_id);
+        Ok(file_ids)
+    }
+
+    async fn delete_file_id(&self, card_id: i32, language: &Language, reversed: bool) -> Result<()> {
+        let bot_id = self.bot_id()?;
+        self.state()
+            .file_ids
+            .remove(&(bot_id, card_id, language.code().to_string(), reversed));
+        Ok(())
+    }
+
+    async fn get_reversed_cards_decks(&self) -> Result<Vec<i32>> {
+        let bot_id = self.bot_id()?;
+        let mut deck_ids: Vec<i32> = self
+            .state()
+            .bot_subscribers(bot_id)
+            .filter(|subscriber| subscriber.reversed_cards)
+            .map(|subscriber| subscriber.deck_id)
+            .collect();
+        deck_ids.sort();
+        deck_ids.dedup();
+        Ok(deck_ids)
+    }
+
+    async fn search_cards(&self, text: &str, language: &Language, deck_id: i32) -> Result<Vec<Card>> {
+        let text = text.to_lowercase();
+        let bot_id = self.bot_id()?;
+        let state = self.state();
+        let mut cards: Vec<Card> = state
+            .cards
+            .iter()
+            .filter(|card| card.deck_id == deck_id)
+            .filter_map(|card| card.translated(language, &state.file_ids, bot_id))
+            .filter(|card| card.name.to_lowercase().contains(&text))
+            .collect();
+        cards.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(cards)
+    }
+
+    async fn count_draws(&self, subscriber_id: i32) -> Result<i64> {
+        Ok(self
+            .state()
+            .draws
+            .iter()
+            .filter(|draw| draw.subscriber_id == subscriber_id)
+            .count() as i64)
+    }
+
+    async fn get_draws(&self, language: &Language, offset: i64, limit: i64, subscriber_id: i32) -> Result<Vec<Draw>> {
+        let bot_id = self.bot_id()?;
+        let state = self.state();
+        let mut draws: Vec<&MemoryDraw> = state
+            .draws
+            .iter()
+            .filter(|draw| draw.subscriber_id == subscriber_id)
+            .collect();
+        draws.sort_by_key(|draw| std::cmp::Reverse((draw.drawn_at, draw.id)));
+        draws
+            .into_iter()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .map(|draw| {
+                //cards without a translation to the language are shown by file names
+                let name = match state.card(draw.card_id, language, bot_id) {
+                    Some(card) => card.name,
+                    None => state
+                        .cards
+                        .iter()
+                        .find(|card| card.id == draw.card_id)
+                        .map(|card| card.filename.clone())
+                        .ok_or(anyhow!("No card {}!", draw.card_id))?,
+                };
+                Ok(Draw {
+                    id: draw.id,
+                    name,
+                    reversed: draw.reversed,
+                    drawn_at: draw.drawn_at,
+                })
+            })
+            .collect()
+    }
+
+    async fn drawn_card(
+        &self,
+        draw_id: i32,
+        language: &Language,
+        descriptions: i32,
+        subscriber_id: i32,
+    ) -> Result<Option<CardData>> {
+        let bot_id = self.bot_id()?;
+        let state = self.state();
+        let draw = match state
+            .draws
+            .iter()
+            .find(|draw| draw.id == draw_id && draw.subscriber_id == subscriber_id)
+        {
+            Some(draw) => draw,
+            None => return Ok(None),
+        };
+        let deck = state.deck(draw.deck_id)?;
+        state
+            .card(draw.card_id, language, bot_id)
+            .map(|card| process::card_data(deck, &card, draw.reversed, language, descriptions))
+            .transpose()
+    }
+
+    async fn get_stats(&self) -> Result<Stats> {
+        let bot_id = self.bot_id()?;
+        let state = self.state();
+        let today = today();
+        let mut languages: Vec<(String, i64)> = vec![];
+        for subscriber in state.chats(bot_id) {
+            match languages
+                .iter_mut()
+                .find(|(code, _)| code == subscriber.language.code())
+            {
+                Some((_, count)) => *count += 1,
+                None => languages.push((subscriber.language.code().to_string(), 1)),
+            }
+        }
+        languages.sort_by(|(a_code, a_count), (b_code, b_count)| {
+            b_count.cmp(a_count).then(a_code.cmp(b_code))
+        });
+        Ok(Stats {
+            subscribers: state.chats(bot_id).count() as i64,
+            new_subscribers: state
+                .chats(bot_id)
+                .filter(|subscriber| subscriber.created_at >= today)
+                .count() as i64,
+            daily_card_subscribers: state
+                .chats(bot_id)
+                .filter(|subscriber| subscriber.daily_card_time.is_some())
+                .count() as i64,
+            draws_today: state
+                .draws
+                .iter()
+                .filter(|draw| draw.drawn_at >= today)
+                .filter(|draw| state.subscriber(draw.subscriber_id).is_ok_and(|subscriber| subscriber.bot_id == bot_id))
+                .count() as i64,
+            languages,
+        })
+    }
+
+    async fn get_subscribers_chat_ids(&self) -> Result<Vec<String>> {
+        Ok(self
+            .state()
+            .chats(self.bot_id()?)
+            .map(|subscriber| subscriber.chat_id.clone())
+            .collect())
+    }
+
+    async fn get_chat_state(&self, chat_id: String) -> Result<Option<ChatState>> {
+        let bot_id = self.bot_id()?;
+        let state = self.state();
+        let subscriber = match state.chats(bot_id).find(|subscriber| subscriber.chat_id == chat_id)
+        {
+            Some(subscriber) => subscriber,
+            None => return Ok(None),
+        };
+        Ok(Some(ChatState {
+            subscriber_id: subscriber.id,
+            created_at: subscriber.created_at,
+            language: subscriber.language.code().to_string(),
+            descriptions_format: subscriber.descriptions_format,
+            reversed_cards: subscriber.reversed_cards,
+            deck_name: state.deck(subscriber.deck_id)?.name.clone(),
+            drawn_cards: subscriber.drawn_cards.len() as i32,
+            daily_card_time: subscriber.daily_card_time,
+            daily_card_utc_offset: subscriber.daily_card_utc_offset,
+            draws: state
+                .draws
+                .iter()
+                .filter(|draw| draw.subscriber_id == subscriber.id)
+                .count() as i64,
+        }))
+    }
+}
@@ -0,0 +1,210 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use sqlx::PgPool;
+use time::{Date, Time};
+
+use crate::admin::{ChatState, Stats};
+use crate::database_interactions;
+use crate::deck_files::CardRecord;
+use crate::file_ids::FileId;
+use crate::markup;
+use crate::process::{Card, CardData, DailyCardSubscriber, Deck, Draw, Language};
+use crate::storage::{BotInfo, Storage};
+
+//queries are checked at compile time against sqlx-data.json
+pub struct PostgresStorage {
+    pool: PgPool,
+    bot_id: Option<i32>,
+}
+
+impl PostgresStorage {
+    pub async fn new(database_path: String, max_connections: u32) -> Result<PostgresStorage> {
+        Ok(PostgresStorage {
+            pool: database_interactions::pool(database_path, max_connections).await?,
+            bot_id: None,
+        })
+    }
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    fn bot_id(&self) -> Result<i32> {
+        self.bot_id.ok_or(anyhow!("The storage is not bound to a bot!"))
+    }
+
+    fn with_bot(&self, bot_id: i32) -> Arc<dyn Storage> {
+        Arc::new(PostgresStorage {
+            pool: self.pool.clone(),
+            bot_id: Some(bot_id),
+        })
+    }
+
+    async fn get_active_bots(&self) -> Result<Vec<BotInfo>> {
+        database_interactions::get_active_bots(&self.pool).await
+    }
+
+    async fn get_subscriber_id(&self, chat_id: String) -> Result<Option<i32>> {
+        database_interactions::get_subscriber_id(chat_id, self.bot_id()?, &self.pool).await
+    }
+
+    async fn create_subscriber(&self, chat_id: String, language: &Language) -> Result<i32> {
+        database_interactions::create_subscriber(self.bot_id()?, chat_id, language, &self.pool).await
+    }
+
+    async fn get_member_id(&self, chat_id: String, user_id: i64) -> Result<Option<i32>> {
+        database_interactions::get_member_id(chat_id, user_id, self.bot_id()?, &self.pool).await
+    }
+
+    async fn create_member(&self, chat_id: String, user_id: i64, subscriber_id: i32) -> Result<i32> {
+        database_interactions::create_member(self.bot_id()?, chat_id, user_id, subscriber_id, &self.pool).await
+    }
+
+    async fn get_per_member_decks(&self, subscriber_id: i32) -> Result<bool> {
+        database_interactions::get_per_member_decks(subscriber_id, &self.pool).await
+    }
+
+    async fn set_per_member_decks(&self, value: bool, subscriber_id: i32) -> Result<()> {
+        database_interactions::set_per_member_decks(value, subscriber_id, self.bot_id()?, &self.pool).await
+    }
+
+    async fn get_language(&self, subscriber_id: i32) -> Result<Language> {
+        database_interactions::get_language(subscriber_id, &self.pool).await
+    }
+
+    async fn set_language(&self, language: &Language, subscriber_id: i32) -> Result<()> {
+        database_interactions::set_language(language, subscriber_id, self.bot_id()?, &self.pool).await
+    }
+
+    async fn get_descriptions_format(&self, subscriber_id: i32) -> Result<i32> {
+        database_interactions::get_descriptions_format(subscriber_id, &self.pool).await
+    }
+
+    async fn set_description(&self, value: i32, subscriber_id: i32) -> Result<()> {
+        database_interactions::set_description(value, subscriber_id, self.bot_id()?, &self.pool).await
+    }
+
+    async fn set_reversed_cards(&self, value: bool, subscriber_id: i32) -> Result<()> {
+        database_interactions::set_reversed_cards(value, subscriber_id, self.bot_id()?, &self.pool).await
+    }
+
+    async fn get_daily_card(&self, subscriber_id: i32) -> Result<(Option<Time>, i32)> {
+        database_interactions::get_daily_card(subscriber_id, &self.pool).await
+    }
+
+    async fn set_daily_card(&self, time: Option<Time>, utc_offset: i32, subscriber_id: i32) -> Result<()> {
+        database_interactions::set_daily_card(time, utc_offset, subscriber_id, self.bot_id()?, &self.pool).await
+    }
+
+    async fn get_daily_card_subscribers(&self) -> Result<Vec<DailyCardSubscriber>> {
+        database_interactions::get_daily_card_subscribers(self.bot_id()?, &self.pool).await
+    }
+
+    async fn mark_daily_card_sent(&self, subscriber_id: i32, date: Date) -> Result<bool> {
+        database_interactions::mark_daily_card_sent(subscriber_id, date, &self.pool).await
+    }
+
+    async fn get_decks(&self) -> Result<Vec<Deck>> {
+        database_interactions::get_decks(&self.pool).await
+    }
+
+    async fn get_deck(&self, subscriber_id: i32) -> Result<Deck> {
+        database_interactions::get_deck(subscriber_id, &self.pool).await
+    }
+
+    async fn set_deck(&self, deck_id: i32, subscriber_id: i32) -> Result<()> {
+        database_interactions::set_deck(deck_id, subscriber_id, self.bot_id()?, &self.pool).await
+    }
+
+    async fn check_cards_table(&self, pictures_path: &str) -> Result<()> {
+        database_interactions::check_cards_table(pictures_path, &self.pool).await
+    }
+
+    async fn get_deck_cards(&self, deck_id: i32) -> Result<Vec<CardRecord>> {
+        database_interactions::get_deck_cards(deck_id, &self.pool).await
+    }
+
+    async fn import_deck(
+        &self,
+        name: &str,
+        path: &str,
+        with_replacement: bool,
+        markup: markup::Format,
+        cards: &[CardRecord],
+        changed_images: &[(String, String)],
+    ) -> Result<i32> {
+        database_interactions::import_deck(name, path, with_replacement, markup, cards, changed_images, &self.pool).await
+    }
+
+    async fn draw_cards(
+        &self,
+        count: usize,
+        spread_id: Option<&str>,
+        language: &Language,
+        subscriber_id: i32,
+    ) -> Result<Option<(Deck, Vec<(Card, bool)>)>> {
+        database_interactions::draw_cards(count, spread_id, language, subscriber_id, self.bot_id()?, &self.pool).await
+    }
+
+    async fn shuffle_cards_back(&self, subscriber_id: i32) -> Result<()> {
+        database_interactions::shuffle_cards_back(subscriber_id, &self.pool).await
+    }
+
+    async fn set_file_id(
+        &self,
+        card_id: i32,
+        language: &Language,
+        reversed: bool,
+        id: String,
+        content_hash: String,
+    ) -> Result<()> {
+        database_interactions::set_file_id(card_id, language, reversed, id, content_hash, self.bot_id()?, &self.pool).await
+    }
+
+    async fn get_file_ids(&self) -> Result<Vec<FileId>> {
+        database_interactions::get_file_ids(self.bot_id()?, &self.pool).await
+    }
+
+    async fn delete_file_id(&self, card_id: i32, language: &Language, reversed: bool) -> Result<()> {
+        database_interactions::delete_file_id(card_id, language, reversed, self.bot_id()?, &self.pool).await
+    }
+
+    async fn get_reversed_cards_decks(&self) -> Result<Vec<i32>> {
+        database_interactions::get_reversed_cards_decks(self.bot_id()?, &self.pool).await
+    }
+
+    async fn search_cards(&self, text: &str, language: &Language, deck_id: i32) -> Result<Vec<Card>> {
+        database_interactions::search_cards(text, language, deck_id, self.bot_id()?, &self.pool).await
+    }
+
+    async fn count_draws(&self, subscriber_id: i32) -> Result<i64> {
+        database_interactions::count_draws(subscriber_id, &self.pool).await
+    }
+
+    async fn get_draws(&self, language: &Language, offset: i64, limit: i64, subscriber_id: i32) -> Result<Vec<Draw>> {
+        database_interactions::get_draws(language, offset, limit, subscriber_id, &self.pool).await
+    }
+
+    async fn drawn_card(
+        &self,
+        draw_id: i32,
+        language: &Language,
+        descriptions: i32,
+        subscriber_id: i32,
+    ) -> Result<Option<CardData>> {
+        database_interactions::drawn_card(draw_id, language, descriptions, subscriber_id, self.bot_id()?, &self.pool).await
+    }
+
+    async fn get_stats(&self) -> Result<Stats> {
+        database_interactions::get_stats(self.bot_id()?, &self.pool).await
+    }
+
+    async fn get_subscribers_chat_ids(&self) -> Result<Vec<String>> {
+        database_interactions::get_subscribers_chat_ids(self.bot_id()?, &self.pool).await
+    }
+
+    async fn get_chat_state(&self, chat_id: String) -> Result<Option<ChatState>> {
+        database_interactions::get_chat_state(chat_id, self.bot_id()?, &self.pool).await
+    }
+}
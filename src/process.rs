@@ -1,17 +1,23 @@
 use anyhow::Result;
-use sqlx::PgPool;
+use rand::seq::{IteratorRandom, SliceRandom};
+use std::{fmt, io::Cursor, path::Path};
 use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile};
+use time::{Date, OffsetDateTime, Time};
 
+use crate::callbacks::Callback;
+use crate::config::Config;
+use crate::error::Error;
 use crate::telegram_interactions::Command;
-use crate::{database_interactions, translations};
+use crate::storage::Storage;
+use crate::{file_ids, markup, spreads, translations};
 
-fn make_keyboard(options: &[&str]) -> InlineKeyboardMarkup {
+fn make_keyboard(options: &[(String, Callback)]) -> InlineKeyboardMarkup {
     let mut keyboard: Vec<Vec<InlineKeyboardButton>> = vec![];
 
     for ops in options.chunks(1) {
         let row = ops
             .iter()
-            .map(|&op| InlineKeyboardButton::callback(op.to_owned(), op.to_owned()))
+            .map(|(text, callback)| InlineKeyboardButton::callback(text.to_owned(), callback.data()))
             .collect();
 
         keyboard.push(row);
@@ -20,113 +26,253 @@ fn make_keyboard(options: &[&str]) -> InlineKeyboardMarkup {
     InlineKeyboardMarkup::new(keyboard)
 }
 
-fn make_main_keyboard(language: Language) -> InlineKeyboardMarkup {
-    let v = match language {
-        Language::Ru => {
-            vec![
-                translations::CARD_RU,
-                translations::SHUFFLE_RU,
-                translations::SETTINGS_RU,
-                translations::ABOUT_RU,
-            ]
-        }
-        Language::En => {
-            vec![
-                translations::CARD_EN,
-                translations::SHUFFLE_EN,
-                translations::SETTINGS_EN,
-                translations::ABOUT_EN,
-            ]
-        }
-    };
-    make_keyboard(&v)
+//buttons with texts from translation by their keys
+fn translated_buttons(
+    buttons: &[(&str, Callback)],
+    language: &Language,
+    translation: &translations::Translation,
+) -> Result<Vec<(String, Callback)>> {
+    buttons
+        .iter()
+        .map(|(key, callback)| Ok((translation.get(key, language)?, callback.clone())))
+        .collect()
 }
 
-fn make_settings_keyboard(language: Language) -> InlineKeyboardMarkup {
-    let v = match language {
-        Language::Ru => {
-            vec![
-                translations::LANGUAGE_RU,
-                translations::DESCRIPTIONS_RU,
-                translations::MAIN_MENU_RU,
-            ]
-        }
-        Language::En => {
-            vec![
-                translations::LANGUAGE_EN,
-                translations::DESCRIPTIONS_EN,
-                translations::MAIN_MENU_EN,
-            ]
-        }
-    };
-    make_keyboard(&v)
+fn make_main_keyboard(
+    language: &Language,
+    translation: &translations::Translation,
+) -> Result<InlineKeyboardMarkup> {
+    Ok(make_keyboard(&translated_buttons(
+        &[
+            (translations::CARD, Callback::Card),
+            (translations::SPREADS, Callback::Spreads),
+            (translations::SHUFFLE, Callback::Shuffle),
+            (translations::HISTORY, Callback::History(0)),
+            (translations::SETTINGS, Callback::Settings),
+            (translations::ABOUT, Callback::About),
+        ],
+        language,
+        translation,
+    )?))
 }
 
-fn make_languages_keyboard(language: Language) -> InlineKeyboardMarkup {
-    let v = match language {
-        Language::Ru => {
-            vec![
-                translations::EN_EN,
-                translations::RU_RU,
-                translations::SETTINGS_RU,
-            ]
-        }
-        Language::En => {
-            vec![
-                translations::EN_EN,
-                translations::RU_RU,
-                translations::SETTINGS_EN,
-            ]
-        }
-    };
-    make_keyboard(&v)
-}
-
-fn make_descriptions_keyboard(language: Language) -> InlineKeyboardMarkup {
-    let v = match language {
-        Language::Ru => {
-            vec![
-                translations::FULL_DESCRIPTIONS_RU,
-                translations::NAMES_ONLY_RU,
-                translations::NO_DESCRIPTIONS_RU,
-                translations::SETTINGS_RU,
-            ]
-        }
-        Language::En => {
-            vec![
-                translations::FULL_DESCRIPTIONS_EN,
-                translations::NAMES_ONLY_EN,
-                translations::NO_DESCRIPTIONS_EN,
-                translations::SETTINGS_EN,
-            ]
-        }
-    };
-    make_keyboard(&v)
+//shared or per member decks can be chosen only in group chats
+fn make_settings_keyboard(
+    language: &Language,
+    translation: &translations::Translation,
+    group: bool,
+) -> Result<InlineKeyboardMarkup> {
+    let mut buttons = vec![
+        (translations::LANGUAGE, Callback::Languages),
+        (translations::DESCRIPTIONS, Callback::Descriptions),
+        (translations::DECK, Callback::Decks),
+        (translations::REVERSED, Callback::Reversed),
+        (translations::DAILY_CARD, Callback::DailyCard(String::new())),
+    ];
+    if group {
+        buttons.push((translations::GROUP_DECKS, Callback::GroupDecks));
+    }
+    buttons.push((translations::MAIN_MENU, Callback::MainMenu));
+    Ok(make_keyboard(&translated_buttons(&buttons, language, translation)?))
+}
+
+fn make_group_decks_keyboard(
+    language: &Language,
+    translation: &translations::Translation,
+) -> Result<InlineKeyboardMarkup> {
+    Ok(make_keyboard(&translated_buttons(
+        &[
+            (translations::SHARED_DECK, Callback::SharedDeck),
+            (translations::MEMBER_DECKS, Callback::MemberDecks),
+            (translations::SETTINGS, Callback::Settings),
+        ],
+        language,
+        translation,
+    )?))
+}
+
+fn make_languages_keyboard(
+    language: &Language,
+    translation: &translations::Translation,
+) -> Result<InlineKeyboardMarkup> {
+    let mut v = translation
+        .languages()
+        .map(|l| Ok((
+            translation.get(translations::LANGUAGE_NAME, &l)?,
+            Callback::Language(l.code().to_string()),
+        )))
+        .collect::<Result<Vec<(String, Callback)>>>()?;
+    v.push((translation.get(translations::SETTINGS, language)?, Callback::Settings));
+    Ok(make_keyboard(&v))
+}
+
+fn make_descriptions_keyboard(
+    language: &Language,
+    translation: &translations::Translation,
+) -> Result<InlineKeyboardMarkup> {
+    Ok(make_keyboard(&translated_buttons(
+        &[
+            (translations::FULL_DESCRIPTIONS, Callback::FullDescriptions),
+            (translations::NAMES_ONLY, Callback::NamesOnly),
+            (translations::NO_DESCRIPTIONS, Callback::NoDescriptions),
+            (translations::SETTINGS, Callback::Settings),
+        ],
+        language,
+        translation,
+    )?))
+}
+
+fn make_reversed_keyboard(
+    language: &Language,
+    translation: &translations::Translation,
+) -> Result<InlineKeyboardMarkup> {
+    Ok(make_keyboard(&translated_buttons(
+        &[
+            (translations::REVERSED_ON, Callback::ReversedOn),
+            (translations::REVERSED_OFF, Callback::ReversedOff),
+            (translations::SETTINGS, Callback::Settings),
+        ],
+        language,
+        translation,
+    )?))
+}
+
+fn make_daily_card_keyboard(
+    language: &Language,
+    translation: &translations::Translation,
+) -> Result<InlineKeyboardMarkup> {
+    let mut v: Vec<(String, Callback)> = DAILY_CARD_TIMES
+        .iter()
+        .map(|time| (time.to_string(), Callback::DailyCard(time.to_string())))
+        .collect();
+    v.push((
+        translation.get(translations::DAILY_CARD_OFF, language)?,
+        Callback::DailyCard(DAILY_CARD_OFF.to_string()),
+    ));
+    v.push((translation.get(translations::SETTINGS, language)?, Callback::Settings));
+    Ok(make_keyboard(&v))
+}
+
+fn make_spreads_keyboard(
+    language: &Language,
+    translation: &translations::Translation,
+    spreads: &spreads::Spreads,
+) -> Result<InlineKeyboardMarkup> {
+    let mut v: Vec<(String, Callback)> = spreads
+        .all()
+        .iter()
+        .map(|spread| (
+            spread.name.get(language, translation.default_language()),
+            Callback::Spread(spread.id.clone()),
+        ))
+        .collect();
+    v.push((translation.get(translations::MAIN_MENU, language)?, Callback::MainMenu));
+    Ok(make_keyboard(&v))
+}
+
+fn make_decks_keyboard(
+    language: &Language,
+    translation: &translations::Translation,
+    decks: &[Deck],
+) -> Result<InlineKeyboardMarkup> {
+    let mut v: Vec<(String, Callback)> = decks
+        .iter()
+        .map(|deck| (deck.name.clone(), Callback::Deck(deck.id)))
+        .collect();
+    v.push((translation.get(translations::SETTINGS, language)?, Callback::Settings));
+    Ok(make_keyboard(&v))
+}
+
+fn make_history_keyboard(
+    language: &Language,
+    translation: &translations::Translation,
+    draws: &[Draw],
+    page: i64,
+    pages_count: i64,
+) -> Result<InlineKeyboardMarkup> {
+    let reversed_mark = translation.get("reversed_mark", language)?;
+    let mut v: Vec<(String, Callback)> = draws
+        .iter()
+        .map(|draw| {
+            let t = draw.drawn_at;
+            let text = format!(
+                "{}-{:02}-{:02} {:02}:{:02} {}{}",
+                t.year(),
+                u8::from(t.month()),
+                t.day(),
+                t.hour(),
+                t.minute(),
+                draw.name,
+                if draw.reversed { reversed_mark.as_str() } else { "" },
+            );
+            (text, Callback::HistoryEntry(draw.id))
+        })
+        .collect();
+    if page > 0 {
+        v.push((translation.get(translations::PREVIOUS_PAGE, language)?, Callback::History(page - 1)));
+    }
+    if page + 1 < pages_count {
+        v.push((translation.get(translations::NEXT_PAGE, language)?, Callback::History(page + 1)));
+    }
+    v.push((translation.get(translations::MAIN_MENU, language)?, Callback::MainMenu));
+    Ok(make_keyboard(&v))
+}
+
+//cards open with their full descriptions, not counted as drawn,
+//the pages are shown only when browsing the whole deck
+fn make_cards_keyboard(
+    language: &Language,
+    translation: &translations::Translation,
+    cards: &[Card],
+    page: i64,
+    pages_count: i64,
+) -> Result<InlineKeyboardMarkup> {
+    let mut v: Vec<(String, Callback)> = cards
+        .iter()
+        .map(|card| (card.name.clone(), Callback::DeckCard(card.id)))
+        .collect();
+    if page > 0 {
+        v.push((translation.get(translations::PREVIOUS_PAGE, language)?, Callback::DeckPage(page - 1)));
+    }
+    if page + 1 < pages_count {
+        v.push((translation.get(translations::NEXT_PAGE, language)?, Callback::DeckPage(page + 1)));
+    }
+    v.push((translation.get(translations::MAIN_MENU, language)?, Callback::MainMenu));
+    Ok(make_keyboard(&v))
+}
+
+pub struct Image {
+    pub input_file: InputFile,
+    //html, the caption of the photo if it fits
+    pub text: String,
+    pub card_id: i32,
+    pub language: Language,
+    pub reversed: bool,
+    //of the uploaded image file, None if the image is sent by its file id
+    pub content_hash: Option<String>,
 }
 
 pub struct Action {
-    pub image_data: Option<(InputFile, String)>,
+    pub images: Vec<Image>,
     pub delete_previous_message: bool,
     pub replacement_text: Option<String>,
     pub new_message_text: String,
     pub keyboard: InlineKeyboardMarkup,
-    pub filename: Option<String>,
 }
 
 impl Action {
     fn new(new_message_text: String, keyboard: InlineKeyboardMarkup) -> Action {
         Action {
-            image_data: None,
+            images: vec![],
             delete_previous_message: false,
             replacement_text: None,
-            new_message_text: new_message_text,
-            keyboard: keyboard,
-            filename: None,
+            new_message_text,
+            keyboard,
         }
     }
 
-    fn set_image_data(mut self, image_data: (InputFile, String)) -> Self {
-        self.image_data = Some(image_data);
+    fn add_image(mut self, image: Image) -> Self {
+        self.images.push(image);
         self
     }
 
@@ -139,230 +285,746 @@ impl Action {
         self.replacement_text = Some(replacement_text);
         self
     }
+}
 
-    fn set_filename(mut self, filename: String) -> Self {
-        self.filename = Some(filename);
-        self
+fn card_image(card_data: CardData, language: &Language, config: &Config) -> Result<Image> {
+    let image_path = Path::new(&config.pictures_path).join(&card_data.image_path);
+    let content_hash = match card_data.file_id {
+        Some(_) => None,
+        None => Some(file_ids::content_hash(&image_path)?),
+    };
+    let input_file = if let Some(file_id) = card_data.file_id {
+        InputFile::file_id(file_id)
+    } else if card_data.reversed {
+        InputFile::memory(rotated_image(&image_path)?)
+            .file_name(format!("reversed_{}", card_data.id))
+    } else {
+        InputFile::file(image_path)
+    };
+    Ok(Image {
+        input_file,
+        text: card_data.message_text,
+        card_id: card_data.id,
+        language: language.clone(),
+        reversed: card_data.reversed,
+        content_hash,
+    })
+}
+
+//the image rotated by 180 degrees, encoded as png
+pub fn rotated_image(image_path: &Path) -> Result<Vec<u8>> {
+    let image = image::open(image_path)?.rotate180();
+    let mut bytes = Cursor::new(vec![]);
+    image.write_to(&mut bytes, image::ImageOutputFormat::Png)?;
+    Ok(bytes.into_inner())
+}
+
+//the chat the command is from and, in group chats, the member who sent it
+pub struct ChatUser {
+    pub chat_id: String,
+    pub member: Option<Member>,
+}
+
+pub struct Member {
+    pub user_id: i64,
+    //shown under the cards the member draws
+    pub name: String,
+    //only administrators change the settings of a group
+    pub is_admin: bool,
+}
+
+impl ChatUser {
+    //private chats and commands not sent by anyone, like the card of the day
+    pub fn chat(chat_id: String) -> ChatUser {
+        ChatUser {
+            chat_id,
+            member: None,
+        }
     }
 }
 
-async fn check_subscriber(chat_id: String, pool: &PgPool) -> Result<i32> {
+async fn check_subscriber(chat_id: String, language: &Language, storage: &dyn Storage) -> Result<i32> {
     let result = if let Some(id) = 
-        database_interactions::get_subscriber_id(chat_id.clone(), pool)
+        storage.get_subscriber_id(chat_id.clone())
+        .await?
+    {
+        id
+    } else {
+        storage.create_subscriber(chat_id, language).await?
+    };
+    Ok(result)
+}
+
+//in group chats with per member decks the member draws from their own deck,
+//otherwise the chat's deck is used
+async fn check_player(user: &ChatUser, subscriber_id: i32, storage: &dyn Storage) -> Result<i32> {
+    let member = match &user.member {
+        Some(member) if storage.get_per_member_decks(subscriber_id).await? => member,
+        _ => return Ok(subscriber_id),
+    };
+    let result = if let Some(id) =
+        storage.get_member_id(user.chat_id.clone(), member.user_id)
         .await?
     {
         id
     } else {
-        let bot_id = database_interactions::get_active_bot_id(pool).await?;
-        database_interactions::create_subscriber(bot_id, chat_id, pool).await?
+        storage.create_member(user.chat_id.clone(), member.user_id, subscriber_id).await?
     };
     Ok(result)
 }
 
+//in group chats the first image says who has drawn the cards
+fn add_drawn_by(
+    mut action: Action,
+    user: &ChatUser,
+    language: &Language,
+    translation: &translations::Translation,
+) -> Result<Action> {
+    if let (Some(member), Some(image)) = (&user.member, action.images.first_mut()) {
+        let drawn_by = translation.get_with("drawn_by", language, &[("user", member.name.clone())])?;
+        image.text = if image.text.is_empty() {
+            drawn_by
+        } else {
+            format!("{}\n{}", drawn_by, image.text)
+        };
+    }
+    Ok(action)
+}
+
 pub async fn process_message(
     command: Command,
-    chat_id: String,
-    pool: &PgPool,
+    user: &ChatUser,
+    storage: &dyn Storage,
     translation: &translations::Translation,
+    spreads: &spreads::Spreads,
+    config: &Config,
 ) -> Result<Action> {
-    let subscriber_id = check_subscriber(chat_id.clone(), pool).await?;
-    let mut language = database_interactions::get_language(subscriber_id, pool).await?;
+    let subscriber_id =
+        check_subscriber(user.chat_id.clone(), translation.default_language(), storage).await?;
+    let mut language = storage.get_language(subscriber_id).await?;
     let descriptions_format =
-        database_interactions::get_descriptions_format(subscriber_id, pool).await?;
+        storage.get_descriptions_format(subscriber_id).await?;
+    let group = user.member.is_some();
+    if user.member.as_ref().is_some_and(|member| !member.is_admin) && command.is_group_setting() {
+        return Ok(Action::new(translation.get("group_settings_admins_only", &language)?,
+            make_main_keyboard(&language, translation)?));
+    }
+    let player_id = match command {
+        Command::Card
+        | Command::Spread(_)
+        | Command::Shuffle
+        | Command::History(_)
+        | Command::HistoryEntry(_) => check_player(user, subscriber_id, storage).await?,
+        _ => subscriber_id,
+    };
     let action: Action = match command {
         Command::Start => 
-            Action::new(translation.get("start", language)?, 
-                make_main_keyboard(language)),
+            Action::new(translation.get("start", &language)?, 
+                make_main_keyboard(&language, translation)?),
         Command::Help => 
-            Action::new(translation.get("help", language)?,
-                make_main_keyboard(language))
+            Action::new(translation.get("help", &language)?,
+                make_main_keyboard(&language, translation)?)
             .set_delete_previous_message(true),
         Command::About => 
-            Action::new(translation.get("choose_your_action", language)?,
-                make_main_keyboard(language))
-            .set_replacement_text(translation.get("description", language)?),
+            Action::new(translation.get("choose_your_action", &language)?,
+                make_main_keyboard(&language, translation)?)
+            .set_replacement_text(translation.get("description", &language)?),
         Command::MainMenu => 
-            Action::new(translation.get("choose_your_action", language)?,
-                make_main_keyboard(language))
+            Action::new(translation.get("choose_your_action", &language)?,
+                make_main_keyboard(&language, translation)?)
             .set_delete_previous_message(true),
         Command::Card => {
-            if let Some(card_data) = database_interactions::random_card_file_name(
-                language,
+            if let Some(card_data) = storage.random_card_file_name(
+                &language,
                 descriptions_format,
-                subscriber_id,
-                pool,
+                player_id,
             )
             .await?
             {
-                let input_file = if let Some(file_id) = card_data.file_id {
-                    InputFile::file_id(file_id)
-                } else {
-                    InputFile::file(format!("pictures/{}/{}", language.to_string(), card_data.filename))
-                };
-
-                Action::new(translation.get(
+                let action = Action::new(translation.get(
                     "choose_your_action",
-                    language,
-                )?, make_main_keyboard(language))
+                    &language,
+                )?, make_main_keyboard(&language, translation)?)
                 .set_delete_previous_message(true)
-                .set_image_data((input_file, card_data.message_text))
-                .set_filename(card_data.filename)
+                .add_image(card_image(card_data, &language, config)?);
+                add_drawn_by(action, user, &language, translation)?
             } else {
-                Action::new(translation.get("no_cards_left", language)?,
-                    make_main_keyboard(language))
+                let deck = storage.get_deck(player_id).await?;
+                Action::new(translation.get_with("no_cards_left", &language, &[("deck", deck.name)])?,
+                    make_main_keyboard(&language, translation)?)
+                .set_delete_previous_message(true)
+            }
+        }
+        Command::Spread(spread_id) => {
+            let spread_id = spread_id.trim();
+            if spread_id.is_empty() {
+                Action::new(translation.get("choose_spread", &language)?,
+                    make_spreads_keyboard(&language, translation, spreads)?)
+                .set_delete_previous_message(true)
+            } else if let Some(spread) = spreads.get(spread_id) {
+                if let Some(cards_data) = storage.random_cards(
+                    spread.positions.len(),
+                    &spread.id,
+                    &language,
+                    descriptions_format,
+                    player_id,
+                )
+                .await?
+                {
+                    let mut action = Action::new(translation.get(
+                        "choose_your_action",
+                        &language,
+                    )?, make_main_keyboard(&language, translation)?)
+                    .set_delete_previous_message(true);
+                    for (position, card_data) in spread.positions.iter().zip(cards_data) {
+                        let mut image = card_image(card_data, &language, config)?;
+                        let position = markup::escape_html(&position.get(&language, translation.default_language()));
+                        image.text = if image.text.is_empty() {
+                            position
+                        } else {
+                            format!("{}\n{}", position, image.text)
+                        };
+                        action = action.add_image(image);
+                    }
+                    add_drawn_by(action, user, &language, translation)?
+                } else {
+                    Action::new(translation.get("not_enough_cards_for_spread", &language)?,
+                        make_main_keyboard(&language, translation)?)
+                    .set_delete_previous_message(true)
+                }
+            } else {
+                Action::new(translation.get("spread_not_found", &language)?,
+                    make_spreads_keyboard(&language, translation, spreads)?)
                 .set_delete_previous_message(true)
             }
         }
         Command::Shuffle => {
-            database_interactions::shuffle_cards_back(subscriber_id, pool).await?;
-            Action::new(translation.get("choose_your_action", language)?,
-                make_main_keyboard(language))
-            .set_replacement_text(translation.get(
+            storage.shuffle_cards_back(player_id).await?;
+            let deck = storage.get_deck(player_id).await?;
+            let remaining = storage.search_cards("", &language, deck.id).await?.len();
+            Action::new(translation.get("choose_your_action", &language)?,
+                make_main_keyboard(&language, translation)?)
+            .set_replacement_text(translation.get_with(
                 "cards_shuffled_back",
-                language,
+                &language,
+                &[("remaining", remaining.to_string()), ("deck", deck.name)],
             )?)
         }
+        Command::History(page) => {
+            let draws_count = storage.count_draws(player_id).await?;
+            let pages_count = (draws_count + HISTORY_PAGE_SIZE - 1) / HISTORY_PAGE_SIZE;
+            let page = page
+                .trim()
+                .parse::<i64>()
+                .unwrap_or(0)
+                .clamp(0, (pages_count - 1).max(0));
+            let draws = storage.get_draws(
+                &language,
+                page * HISTORY_PAGE_SIZE,
+                HISTORY_PAGE_SIZE,
+                player_id,
+            )
+            .await?;
+            let text = if draws.is_empty() {
+                translation.get("history_empty", &language)?
+            } else {
+                format!("{} ({}/{})", translation.get("history", &language)?, page + 1, pages_count)
+            };
+            Action::new(text,
+                make_history_keyboard(&language, translation, &draws, page, pages_count)?)
+            .set_delete_previous_message(true)
+        }
+        Command::HistoryEntry(draw_id) => {
+            let card_data = match draw_id.trim().parse::<i32>() {
+                Ok(draw_id) => storage.drawn_card(
+                    draw_id,
+                    &language,
+                    descriptions_format,
+                    player_id,
+                )
+                .await?,
+                Err(_) => None,
+            };
+            if let Some(card_data) = card_data {
+                Action::new(translation.get("choose_your_action", &language)?,
+                    make_main_keyboard(&language, translation)?)
+                .add_image(card_image(card_data, &language, config)?)
+            } else {
+                Action::new(translation.get("draw_not_found", &language)?,
+                    make_main_keyboard(&language, translation)?)
+            }
+        }
+        Command::Deck(page) => {
+            let deck = storage.get_deck(subscriber_id).await?;
+            let cards = storage.search_cards("", &language, deck.id).await?;
+            let pages_count = (cards.len() as i64 + DECK_PAGE_SIZE - 1) / DECK_PAGE_SIZE;
+            let page = page
+                .trim()
+                .parse::<i64>()
+                .unwrap_or(0)
+                .clamp(0, (pages_count - 1).max(0));
+            let page_cards: Vec<Card> = cards
+                .into_iter()
+                .skip((page * DECK_PAGE_SIZE) as usize)
+                .take(DECK_PAGE_SIZE as usize)
+                .collect();
+            let text = if page_cards.is_empty() {
+                translation.get("deck_empty", &language)?
+            } else {
+                format!("{} ({}/{})", markup::escape_html(&deck.name), page + 1, pages_count)
+            };
+            Action::new(text,
+                make_cards_keyboard(&language, translation, &page_cards, page, pages_count)?)
+            .set_delete_previous_message(true)
+        }
+        Command::DeckCard(card_id) => {
+            let deck = storage.get_deck(subscriber_id).await?;
+            let card = match card_id.trim().parse::<i32>() {
+                Ok(card_id) => storage
+                    .search_cards("", &language, deck.id)
+                    .await?
+                    .into_iter()
+                    .find(|card| card.id == card_id),
+                Err(_) => None,
+            };
+            if let Some(card) = card {
+                let card_data = card_data(&deck, &card, false, &language, FULL_DESCRIPTIONS)?;
+                Action::new(translation.get("choose_your_action", &language)?,
+                    make_main_keyboard(&language, translation)?)
+                .add_image(card_image(card_data, &language, config)?)
+            } else {
+                Action::new(translation.get("card_not_found", &language)?,
+                    make_main_keyboard(&language, translation)?)
+            }
+        }
+        Command::Find(text) => {
+            let text = text.trim();
+            if text.is_empty() {
+                Action::new(translation.get("find_usage", &language)?,
+                    make_main_keyboard(&language, translation)?)
+            } else {
+                let deck = storage.get_deck(subscriber_id).await?;
+                let mut cards = storage.search_cards(text, &language, deck.id).await?;
+                if cards.len() == 1 {
+                    let card_data = card_data(&deck, &cards[0], false, &language, FULL_DESCRIPTIONS)?;
+                    Action::new(translation.get("choose_your_action", &language)?,
+                        make_main_keyboard(&language, translation)?)
+                    .add_image(card_image(card_data, &language, config)?)
+                } else if cards.is_empty() {
+                    Action::new(translation.get("card_not_found", &language)?,
+                        make_main_keyboard(&language, translation)?)
+                } else {
+                    cards.truncate(DECK_PAGE_SIZE as usize);
+                    Action::new(translation.get("found_cards", &language)?,
+                        make_cards_keyboard(&language, translation, &cards, 0, 1)?)
+                }
+            }
+        }
+        Command::DailyCard(argument) => {
+            let argument = argument.trim();
+            let (daily_card_time, utc_offset) =
+                storage.get_daily_card(subscriber_id).await?;
+            let replacement_text = if argument.is_empty() {
+                None
+            } else if argument == DAILY_CARD_OFF {
+                storage.set_daily_card(None, utc_offset, subscriber_id).await?;
+                Some(translation.get("daily_card_off", &language)?)
+            } else if let Some((time, new_utc_offset)) = parse_daily_card(argument) {
+                let utc_offset = new_utc_offset.unwrap_or(utc_offset);
+                storage.set_daily_card(Some(time), utc_offset, subscriber_id)
+                    .await?;
+                Some(format!(
+                    "{} {}",
+                    translation.get("daily_card_on", &language)?,
+                    format_daily_card(time, utc_offset)
+                ))
+            } else {
+                Some(translation.get("daily_card_wrong_format", &language)?)
+            };
+            if let Some(replacement_text) = replacement_text {
+                Action::new(translation.get("daily_card_settings", &language)?,
+                    make_daily_card_keyboard(&language, translation)?)
+                .set_replacement_text(replacement_text)
+            } else {
+                let current = match daily_card_time {
+                    Some(time) => format!(
+                        "{} {}",
+                        translation.get("daily_card_on", &language)?,
+                        format_daily_card(time, utc_offset)
+                    ),
+                    None => translation.get("daily_card_off", &language)?,
+                };
+                Action::new(format!(
+                    "{}\n{}",
+                    translation.get("daily_card_settings", &language)?,
+                    current
+                ), make_daily_card_keyboard(&language, translation)?)
+                .set_delete_previous_message(true)
+            }
+        }
         Command::Settings => 
-            Action::new(translation.get("settings", language)?,
-                make_settings_keyboard(language))
-            .set_delete_previous_message(true),
-        Command::Language => 
-            Action::new(translation.get("language_settings", language)?,
-                make_languages_keyboard(language))
+            Action::new(translation.get("settings", &language)?,
+                make_settings_keyboard(&language, translation, group)?)
             .set_delete_previous_message(true),
+        Command::GroupDecks => {
+            let current = if storage.get_per_member_decks(subscriber_id).await? {
+                translation.get("member_decks_set", &language)?
+            } else {
+                translation.get("shared_deck_set", &language)?
+            };
+            Action::new(format!(
+                "{}\n{}",
+                translation.get("group_decks_settings", &language)?,
+                current
+            ), make_group_decks_keyboard(&language, translation)?)
+            .set_delete_previous_message(true)
+        }
+        Command::SharedDeck => {
+            storage.set_per_member_decks(false, subscriber_id).await?;
+            Action::new(translation.get("group_decks_settings", &language)?,
+                make_group_decks_keyboard(&language, translation)?)
+            .set_replacement_text(translation.get("shared_deck_set", &language)?)
+        }
+        Command::MemberDecks => {
+            storage.set_per_member_decks(true, subscriber_id).await?;
+            Action::new(translation.get("group_decks_settings", &language)?,
+                make_group_decks_keyboard(&language, translation)?)
+            .set_replacement_text(translation.get("member_decks_set", &language)?)
+        }
         Command::Description => 
-            Action::new(translation.get("descriptions_settings", language)?,
-                make_descriptions_keyboard(language))
+            Action::new(translation.get("descriptions_settings", &language)?,
+                make_descriptions_keyboard(&language, translation)?)
             .set_delete_previous_message(true),
-        Command::En => {
-            database_interactions::set_language(Language::En, subscriber_id, pool).await?;
-            language = Language::En;
-            Action::new(translation.get("language_settings", language)?,
-                make_languages_keyboard(language))
-            .set_replacement_text(translation.get(
-                "language_set_to_english",
-                language,
-            )?)
+        Command::Decks(deck_id) => {
+            let decks = storage.get_decks().await?;
+            let deck = deck_id
+                .trim()
+                .parse::<i32>()
+                .ok()
+                .and_then(|deck_id| decks.iter().find(|deck| deck.id == deck_id));
+            if let Some(deck) = deck {
+                storage.set_deck(deck.id, subscriber_id).await?;
+                Action::new(translation.get("deck_settings", &language)?,
+                    make_decks_keyboard(&language, translation, &decks)?)
+                .set_replacement_text(
+                    translation.get_with("deck_selected", &language, &[("deck", deck.name.clone())])?,
+                )
+            } else {
+                let current_deck = storage.get_deck(subscriber_id).await?;
+                Action::new(format!(
+                    "{}\n{}",
+                    translation.get("deck_settings", &language)?,
+                    translation.get_with("current_deck", &language, &[("deck", current_deck.name)])?
+                ), make_decks_keyboard(&language, translation, &decks)?)
+                .set_delete_previous_message(true)
+            }
         }
-        Command::Ru => {
-            database_interactions::set_language(Language::Ru, subscriber_id, pool).await?;
-            language = Language::Ru;
-            Action::new(translation.get("language_settings", language)?,
-                make_languages_keyboard(language))
-            .set_replacement_text(translation.get(
-                "language_set_to_russian",
-                language,
-            )?)
+        Command::Language(code) => {
+            let code = code.trim();
+            if translation.has_language(code) {
+                language = Language::new(code);
+                storage.set_language(&language, subscriber_id).await?;
+                Action::new(translation.get("language_settings", &language)?,
+                    make_languages_keyboard(&language, translation)?)
+                .set_replacement_text(translation.get(
+                    "language_set",
+                    &language,
+                )?)
+            } else {
+                Action::new(translation.get("language_settings", &language)?,
+                    make_languages_keyboard(&language, translation)?)
+                .set_delete_previous_message(true)
+            }
         }
         Command::FullDescription => {
-            database_interactions::set_description(FULL_DESCRIPTIONS, subscriber_id, pool).await?;
+            storage.set_description(FULL_DESCRIPTIONS, subscriber_id).await?;
             Action::new(translation.get(
                 "descriptions_settings",
-                language,
+                &language,
             )?,
-            make_descriptions_keyboard(language))
+            make_descriptions_keyboard(&language, translation)?)
             .set_replacement_text(translation.get(
                 "full_descriptions_will_be_shown",
-                language,
+                &language,
             )?)
         }
         Command::NamesOnly => {
-            database_interactions::set_description(NAMES_ONLY, subscriber_id, pool).await?;
+            storage.set_description(NAMES_ONLY, subscriber_id).await?;
             Action::new(translation.get(
                 "descriptions_settings",
-                language,
+                &language,
             )?,
-            make_descriptions_keyboard(language))
+            make_descriptions_keyboard(&language, translation)?)
             .set_replacement_text(translation.get(
                 "names_only_will_be_shown",
-                language,
+                &language,
             )?)
         }
         Command::NoDescription => {
-            database_interactions::set_description(NO_DESCRIPTIONS, subscriber_id, pool).await?;
+            storage.set_description(NO_DESCRIPTIONS, subscriber_id).await?;
             Action::new(translation.get(
                 "descriptions_settings",
-                language,
+                &language,
             )?,
-            make_descriptions_keyboard(language))
+            make_descriptions_keyboard(&language, translation)?)
             .set_replacement_text(translation.get(
                 "no_descriptions",
-                language,
+                &language,
+            )?)
+        }
+        Command::Reversed => 
+            Action::new(translation.get("reversed_settings", &language)?,
+                make_reversed_keyboard(&language, translation)?)
+            .set_delete_previous_message(true),
+        Command::ReversedOn => {
+            storage.set_reversed_cards(true, subscriber_id).await?;
+            Action::new(translation.get(
+                "reversed_settings",
+                &language,
+            )?,
+            make_reversed_keyboard(&language, translation)?)
+            .set_replacement_text(translation.get(
+                "reversed_cards_enabled",
+                &language,
+            )?)
+        }
+        Command::ReversedOff => {
+            storage.set_reversed_cards(false, subscriber_id).await?;
+            Action::new(translation.get(
+                "reversed_settings",
+                &language,
+            )?,
+            make_reversed_keyboard(&language, translation)?)
+            .set_replacement_text(translation.get(
+                "reversed_cards_disabled",
+                &language,
             )?)
         }
     };
     Ok(action)
 }
 
+//cards for the inline mode from the deck of the user's private chat:
+//a random one for the empty query, otherwise the ones with the query in their names.
+//Only the cards already uploaded to Telegram can be sent without a chat,
+//and inline queries come on every keystroke, so the cards are not counted as drawn
+pub async fn inline_cards(
+    query: &str,
+    user_id: String,
+    language_code: Option<&str>,
+    storage: &dyn Storage,
+    translation: &translations::Translation,
+) -> Result<Vec<CardData>> {
+    let language = match language_code {
+        Some(code) if translation.has_language(code) => Language::new(code),
+        _ => translation.default_language().clone(),
+    };
+    let subscriber_id = check_subscriber(user_id, &language, storage).await?;
+    let language = storage.get_language(subscriber_id).await?;
+    let descriptions_format = storage.get_descriptions_format(subscriber_id).await?;
+    let deck = storage.get_deck(subscriber_id).await?;
+    let query = query.trim();
+    let cards = storage
+        .search_cards(query, &language, deck.id)
+        .await?
+        .into_iter()
+        .filter(|card| card.telegram_file_id.is_some());
+    let cards: Vec<Card> = if query.is_empty() {
+        cards.choose(&mut rand::thread_rng()).into_iter().collect()
+    } else {
+        cards.take(INLINE_RESULTS_LIMIT).collect()
+    };
+    cards
+        .iter()
+        .map(|card| card_data(&deck, card, false, &language, descriptions_format))
+        .collect()
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct Card {
     pub id: i32,
     pub filename: String,
-    pub name_en: String,
-    pub description_en: String,
-    pub name_ru: String,
-    pub description_ru: String,
-    pub telegram_file_id_en: Option<String>,
-    pub telegram_file_id_ru: Option<String>,
-}
-
-impl Card {
-    pub fn get_name(&self, language: Language) -> String {
-        match language {
-            Language::En => {
-                self.name_en.clone()
-            }
-            Language::Ru => {
-                self.name_ru.clone()
-            }
-        }
-    }
+    pub name: String,
+    pub description: String,
+    pub telegram_file_id: Option<String>,
+    pub reversed_name: Option<String>,
+    pub reversed_description: Option<String>,
+    pub reversed_telegram_file_id: Option<String>,
+    //cards with bigger weights are drawn more often
+    pub weight: i32,
+}
 
-    pub fn get_description(&self, language: Language) -> String {
-        match language {
-            Language::En => {
-                self.description_en.clone()
-            }
-            Language::Ru => {
-                self.description_ru.clone()
-            }
-        }
+//chooses `count` distinct cards, each one with the chance proportional to its weight
+//among the cards which are not chosen yet, None if there are not enough cards
+pub fn choose_cards(cards: Vec<Card>, count: usize) -> Option<Vec<Card>> {
+    if cards.len() < count || count == 0 {
+        return None;
     }
+    let indices: Vec<usize> = (0..cards.len()).collect();
+    let mut chosen: Vec<usize> = indices
+        .choose_multiple_weighted(&mut rand::thread_rng(), count, |&index| cards[index].weight as f64)
+        .ok()?
+        .copied()
+        .collect();
+    //the order matters for spreads, so it is random too
+    chosen.shuffle(&mut rand::thread_rng());
+    let mut cards: Vec<Option<Card>> = cards.into_iter().map(Some).collect();
+    Some(chosen.into_iter().filter_map(|index| cards[index].take()).collect())
+}
 
-    pub fn get_file_id(&self, language: Language) -> Option<String> {
-        match language {
-            Language::En => {
-                self.telegram_file_id_en.clone()
-            }
-            Language::Ru => {
-                self.telegram_file_id_ru.clone()
-            }
-        }
-    }
+//the reversed texts are used for reversed cards if they are present in the deck
+pub fn card_data(
+    deck: &Deck,
+    card: &Card,
+    reversed: bool,
+    language: &Language,
+    descriptions: i32,
+) -> Result<CardData> {
+    let (name, description, file_id) = if reversed {
+        (
+            card.reversed_name.as_ref().unwrap_or(&card.name),
+            card.reversed_description.as_ref().unwrap_or(&card.description),
+            card.reversed_telegram_file_id.clone(),
+        )
+    } else {
+        (&card.name, &card.description, card.telegram_file_id.clone())
+    };
+    //html with the name in bold, descriptions are checked on import,
+    //a malformed one that got past it is shown as it is
+    let description = markup::render(description, deck.markup)
+        .unwrap_or_else(|_| markup::escape_html(description));
+    let message_text = match descriptions {
+        FULL_DESCRIPTIONS => format!("{}\n{}", markup::bold(name), description),
+        NAMES_ONLY => markup::bold(name),
+        NO_DESCRIPTIONS => "".to_string(),
+        _ => return Err(Error::Data(format!("Descriptions format {} is not supported!", descriptions)).into()),
+    };
+
+    Ok(CardData {
+        id: card.id,
+        image_path: Path::new(&deck.path)
+            .join(language.code())
+            .join(&card.filename)
+            .to_string_lossy()
+            .to_string(),
+        message_text,
+        file_id,
+        reversed,
+    })
 }
 
 pub struct CardData {
+    pub id: i32,
     pub file_id: Option<String>,
-    pub filename: String,
+    //relative to the pictures path
+    pub image_path: String,
+    //html
     pub message_text: String,
+    pub reversed: bool,
+}
+
+pub struct Draw {
+    pub id: i32,
+    pub name: String,
+    pub reversed: bool,
+    pub drawn_at: OffsetDateTime,
 }
 
-#[derive(sqlx::Type, Debug, Clone, Copy, Hash, PartialEq, Eq)]
-#[sqlx(type_name = "language", rename_all = "lowercase")]
-pub enum Language {
-    En,
-    Ru,
+pub struct DailyCardSubscriber {
+    pub subscriber_id: i32,
+    pub chat_id: String,
+    pub daily_card_time: Time,
+    pub daily_card_utc_offset: i32,
+    pub daily_card_sent_on: Option<Date>,
 }
 
+//parses "09:00" or "09:00 +03:00", the utc offset is returned in minutes
+fn parse_daily_card(argument: &str) -> Option<(Time, Option<i32>)> {
+    let mut parts = argument.split_whitespace();
+    let (hours, minutes) = parts.next()?.split_once(':')?;
+    let time = Time::from_hms(hours.parse().ok()?, minutes.parse().ok()?, 0).ok()?;
+    let utc_offset = match parts.next() {
+        Some(offset) => Some(parse_utc_offset(offset)?),
+        None => None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((time, utc_offset))
+}
+
+//parses "+03:00", "-5", "UTC+5:30" into minutes
+fn parse_utc_offset(offset: &str) -> Option<i32> {
+    let offset = offset
+        .strip_prefix("UTC")
+        .or_else(|| offset.strip_prefix("utc"))
+        .unwrap_or(offset);
+    let (sign, offset) = match offset.strip_prefix('-') {
+        Some(offset) => (-1, offset),
+        None => (1, offset.strip_prefix('+').unwrap_or(offset)),
+    };
+    let (hours, minutes) = offset.split_once(':').unwrap_or((offset, "0"));
+    let (hours, minutes): (i32, i32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    let utc_offset = sign * (hours * 60 + minutes);
+    if !(0..60).contains(&minutes) || !(-12 * 60..=14 * 60).contains(&utc_offset) {
+        return None;
+    }
+    Some(utc_offset)
+}
+
+pub fn format_daily_card(time: Time, utc_offset: i32) -> String {
+    format!(
+        "{:02}:{:02} UTC{}{:02}:{:02}",
+        time.hour(),
+        time.minute(),
+        if utc_offset < 0 { '-' } else { '+' },
+        utc_offset.abs() / 60,
+        utc_offset.abs() % 60
+    )
+}
+
+#[derive(Clone)]
+pub struct Deck {
+    pub id: i32,
+    pub name: String,
+    pub path: String,
+    //drawn cards are not put aside until shuffled, so they can be drawn again
+    pub with_replacement: bool,
+    //the markup format of the card descriptions
+    pub markup: markup::Format,
+}
+
+//language code, the same as the name of the folder with its translation and pictures
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct Language(String);
+
 impl Language {
-    fn to_string(&self) -> String {
-        match self {
-            Language::En => { "en".to_string() },
-            Language::Ru => { "ru".to_string() },
-        }
+    pub fn new(code: &str) -> Language {
+        Language(code.to_string())
+    }
+
+    pub fn code(&self) -> &str {
+        &self.0
     }
 }
 
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub const HISTORY_PAGE_SIZE: i64 = 5;
+//cards per page of the deck browser, also the most cards /find lists
+pub const DECK_PAGE_SIZE: i64 = 10;
+
+//telegram doesn't accept more results for an inline query
+const INLINE_RESULTS_LIMIT: usize = 50;
+
+//local times offered on the card of the day keyboard, any other can be set by command
+const DAILY_CARD_TIMES: [&str; 4] = ["08:00", "09:00", "12:00", "21:00"];
+const DAILY_CARD_OFF: &str = "off";
+
 pub const FULL_DESCRIPTIONS: i32 = 0;
 pub const NAMES_ONLY: i32 = 1;
-pub const NO_DESCRIPTIONS: i32 = 2;
\ No newline at end of file
+pub const NO_DESCRIPTIONS: i32 = 2;
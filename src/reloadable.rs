@@ -0,0 +1,30 @@
+use std::sync::{Arc, RwLock};
+
+//data loaded from files which can be reloaded without restart,
+//handlers take a snapshot with get() and keep using it till they finish
+pub struct Reloadable<T> {
+    value: RwLock<Arc<T>>,
+}
+
+impl<T> Reloadable<T> {
+    pub fn new(value: T) -> Reloadable<T> {
+        Reloadable {
+            value: RwLock::new(Arc::new(value)),
+        }
+    }
+
+    pub fn get(&self) -> Arc<T> {
+        match self.value.read() {
+            Ok(value) => value.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    pub fn set(&self, value: T) {
+        let value = Arc::new(value);
+        match self.value.write() {
+            Ok(mut guard) => *guard = value,
+            Err(poisoned) => *poisoned.into_inner() = value,
+        }
+    }
+}
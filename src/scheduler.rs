@@ -0,0 +1,97 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use teloxide::{prelude::*, types::ParseMode};
+use time::{OffsetDateTime, UtcOffset};
+
+use crate::config::Config;
+use crate::error::Error;
+use crate::reloadable::Reloadable;
+use crate::telegram_interactions::{self, Command};
+use crate::storage::Storage;
+use crate::{process, spreads, translations};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+//sends cards of the day, the sent date is saved before sending,
+//so after a restart or downtime every chat gets at most one card per local day
+pub async fn run(
+    bot: Bot,
+    storage: Arc<dyn Storage>,
+    translation: Arc<Reloadable<translations::Translation>>,
+    spreads: Arc<Reloadable<spreads::Spreads>>,
+    config: Arc<Config>,
+) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let (translation, spreads) = (translation.get(), spreads.get());
+        if let Err(e) = send_daily_cards(&bot, &*storage, &translation, &spreads, &config).await {
+            tracing::error!("Error sending daily cards: {e:?}");
+        }
+    }
+}
+
+async fn send_daily_cards(
+    bot: &Bot,
+    storage: &dyn Storage,
+    translation: &translations::Translation,
+    spreads: &spreads::Spreads,
+    config: &Config,
+) -> Result<()> {
+    let now = OffsetDateTime::now_utc();
+    for subscriber in storage.get_daily_card_subscribers().await? {
+        let local_now = now.to_offset(UtcOffset::from_whole_seconds(
+            subscriber.daily_card_utc_offset * 60,
+        )?);
+        let sent_today = subscriber.daily_card_sent_on == Some(local_now.date());
+        if sent_today || local_now.time() < subscriber.daily_card_time {
+            continue;
+        }
+        if !storage.mark_daily_card_sent(
+            subscriber.subscriber_id,
+            local_now.date(),
+        )
+        .await?
+        {
+            continue;
+        }
+        if let Err(e) = send_daily_card(bot, &subscriber, storage, translation, spreads, config).await {
+            let error = Error::from(e);
+            error.log(&format!("Error sending daily card to {}", subscriber.chat_id));
+            //chats which blocked the bot don't get cards anymore
+            if let Error::Blocked(_) = error {
+                storage
+                    .set_daily_card(None, subscriber.daily_card_utc_offset, subscriber.subscriber_id)
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn send_daily_card(
+    bot: &Bot,
+    subscriber: &process::DailyCardSubscriber,
+    storage: &dyn Storage,
+    translation: &translations::Translation,
+    spreads: &spreads::Spreads,
+    config: &Config,
+) -> Result<()> {
+    let chat_id = ChatId(subscriber.chat_id.parse()?);
+    let language = storage.get_language(subscriber.subscriber_id).await?;
+    bot.send_message(chat_id, translation.get("daily_card", &language)?)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    let action = process::process_message(
+        Command::Card,
+        &process::ChatUser::chat(subscriber.chat_id.clone()),
+        storage,
+        translation,
+        spreads,
+        config,
+    )
+    .await?;
+    telegram_interactions::process_action(action, chat_id, None, bot.clone(), None, storage, config).await?;
+    Ok(())
+}
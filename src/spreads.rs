@@ -0,0 +1,54 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
+
+use crate::callbacks::{Callback, MAX_CALLBACK_DATA_LENGTH};
+use crate::process;
+
+//texts by language code
+#[derive(Debug, Deserialize)]
+#[serde(transparent)]
+pub struct Label(HashMap<String, String>);
+
+impl Label {
+    //falls back to the default language, if there is no text for the given one
+    pub fn get(&self, language: &process::Language, default_language: &process::Language) -> String {
+        self.0
+            .get(language.code())
+            .or_else(|| self.0.get(default_language.code()))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Spread {
+    pub id: String,
+    pub name: Label,
+    pub positions: Vec<Label>,
+}
+
+pub struct Spreads {
+    spreads: Vec<Spread>,
+}
+
+impl Spreads {
+    pub fn all(&self) -> &[Spread] {
+        &self.spreads
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Spread> {
+        self.spreads.iter().find(|s| s.id == id)
+    }
+}
+
+pub fn spreads(pictures_path: &str) -> Result<Spreads> {
+    let file_string = std::fs::read_to_string(Path::new(pictures_path).join("spreads.yml"))?;
+    let spreads: Vec<Spread> = serde_yaml::from_str(&file_string)?;
+    for spread in &spreads {
+        if Callback::Spread(spread.id.clone()).data().len() > MAX_CALLBACK_DATA_LENGTH {
+            return Err(anyhow!("Spread id {} is too long!", spread.id));
+        }
+    }
+    Ok(Spreads { spreads })
+}
@@ -0,0 +1,1151 @@
+use std::{path::Path, str::FromStr, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow},
+    Row,
+};
+use time::{format_description::FormatItem, macros::format_description, Date, OffsetDateTime, PrimitiveDateTime, Time};
+
+use crate::admin::{ChatState, Stats};
+use crate::deck_files;
+use crate::file_ids::{self, FileId};
+use crate::markup;
+use crate::process::{self, Card, CardData, DailyCardSubscriber, Deck, Draw, Language};
+use crate::storage::{BotInfo, Storage};
+
+//timestamps are stored as text in UTC with a fixed number of digits,
+//so they are compared and sorted as strings correctly
+const TIMESTAMP_FORMAT: &[FormatItem<'_>] =
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:6]");
+
+//queries are checked at runtime, since sqlx-data.json is for Postgres only
+pub struct SqliteStorage {
+    pool: SqlitePool,
+    bot_id: Option<i32>,
+}
+
+impl SqliteStorage {
+    //the database file is created and migrated on start, if needed
+    pub async fn new(database_path: &str, max_connections: u32) -> Result<SqliteStorage> {
+        let options = SqliteConnectOptions::from_str(database_path)?.create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await?;
+        sqlx::migrate!("./migrations_sqlite").run(&pool).await?;
+        Ok(SqliteStorage { pool, bot_id: None })
+    }
+}
+
+fn encode_timestamp(t: OffsetDateTime) -> Result<String> {
+    Ok(t.to_offset(time::UtcOffset::UTC).format(TIMESTAMP_FORMAT)?)
+}
+
+fn decode_timestamp(text: &str) -> Result<OffsetDateTime> {
+    Ok(PrimitiveDateTime::parse(text, TIMESTAMP_FORMAT)?.assume_utc())
+}
+
+fn today() -> Result<String> {
+    encode_timestamp(OffsetDateTime::now_utc().replace_time(Time::MIDNIGHT))
+}
+
+//drawn_cards is a json array, as sqlite has no arrays
+fn decode_drawn_cards(text: &str) -> Result<Vec<i32>> {
+    Ok(serde_json::from_str(text)?)
+}
+
+fn card_from_row(row: &SqliteRow) -> Result<Card> {
+    Ok(Card {
+        id: row.try_get("id")?,
+        filename: row.try_get("filename")?,
+        name: row.try_get("name")?,
+        description: row.try_get("description")?,
+        telegram_file_id: row.try_get("telegram_file_id")?,
+        reversed_name: row.try_get("reversed_name")?,
+        reversed_description: row.try_get("reversed_description")?,
+        reversed_telegram_file_id: row.try_get("reversed_telegram_file_id")?,
+        weight: row.try_get("weight")?,
+    })
+}
+
+fn deck_from_row(row: &SqliteRow) -> Result<Deck> {
+    Ok(Deck {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        path: row.try_get("path")?,
+        with_replacement: row.try_get("with_replacement")?,
+        markup: row.try_get("markup")?,
+    })
+}
+
+//cards with their translations and the file ids of the bot, which is bound as ?3
+const SELECT_CARDS: &str = "
+    SELECT cards.id, cards.filename, card_translations.name,
+    card_translations.description, file_ids.file_id AS telegram_file_id,
+    card_translations.reversed_name, card_translations.reversed_description,
+    reversed_file_ids.file_id AS reversed_telegram_file_id, cards.weight
+    FROM cards
+    JOIN card_translations ON card_translations.card_id = cards.id
+    LEFT JOIN file_ids ON file_ids.card_id = cards.id
+    AND file_ids.language = card_translations.language
+    AND file_ids.bot_id = ?3 AND NOT file_ids.reversed
+    LEFT JOIN file_ids AS reversed_file_ids ON reversed_file_ids.card_id = cards.id
+    AND reversed_file_ids.language = card_translations.language
+    AND reversed_file_ids.bot_id = ?3 AND reversed_file_ids.reversed
+";
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    fn bot_id(&self) -> Result<i32> {
+        self.bot_id.ok_or(anyhow!("The storage is not bound to a bot!"))
+    }
+
+    fn with_bot(&self, bot_id: i32) -> Arc<dyn Storage> {
+        Arc::new(SqliteStorage {
+            pool: self.pool.clone(),
+            bot_id: Some(bot_id),
+        })
+    }
+
+    async fn get_active_bots(&self) -> Result<Vec<BotInfo>> {
+        let rows = sqlx::query(
+            "
+            SELECT id, name, key FROM bots WHERE active=true
+            ORDER BY id;
+        ",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(BotInfo {
+                    id: row.try_get("id")?,
+                    name: row.try_get("name")?,
+                    key: row.try_get("key")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_subscriber_id(&self, chat_id: String) -> Result<Option<i32>> {
+        let query_result = sqlx::query_scalar(
+            "
+            SELECT id FROM subscribers
+            WHERE chat_id=?1 AND user_id IS NULL AND bot_id=?2;
+        ",
+        )
+        .bind(chat_id)
+        .bind(self.bot_id()?)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(query_result)
+    }
+
+    async fn create_subscriber(&self, chat_id: String, language: &Language) -> Result<i32> {
+        let bot_id = self.bot_id()?;
+        let mut tx = self.pool.begin().await?;
+        let subscriber_id: Option<i32> = sqlx::query_scalar(
+            "
+                INSERT INTO subscribers (bot_id, chat_id, created_at)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT DO NOTHING
+                RETURNING id;
+            ",
+        )
+        .bind(bot_id)
+        .bind(&chat_id)
+        .bind(encode_timestamp(OffsetDateTime::now_utc())?)
+        .fetch_optional(&mut tx)
+        .await?;
+        let subscriber_id = match subscriber_id {
+            Some(subscriber_id) => subscriber_id,
+            //another message of the chat has created it meanwhile
+            None => {
+                tx.rollback().await?;
+                return self
+                    .get_subscriber_id(chat_id)
+                    .await?
+                    .ok_or(anyhow!("The subscriber is neither created nor found!"));
+            }
+        };
+        sqlx::query(
+            "
+                INSERT INTO bots_subscribers (bot_id, subscriber_id)
+                VALUES (?1, ?2);
+            ",
+        )
+        .bind(bot_id)
+        .bind(subscriber_id)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query(
+            "
+                INSERT INTO chats_states
+                (bot_id, subscriber_id, drawn_cards, language, descriptions_format)
+                VALUES (?1, ?2, '[]', ?3, ?4);
+            ",
+        )
+        .bind(bot_id)
+        .bind(subscriber_id)
+        .bind(language.code())
+        .bind(process::FULL_DESCRIPTIONS)
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(subscriber_id)
+    }
+
+    async fn get_member_id(&self, chat_id: String, user_id: i64) -> Result<Option<i32>> {
+        let query_result = sqlx::query_scalar(
+            "
+            SELECT id FROM subscribers
+            WHERE chat_id=?1 AND user_id=?2 AND bot_id=?3;
+        ",
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .bind(self.bot_id()?)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(query_result)
+    }
+
+    //a member of a group chat gets the deck settings of the chat
+    async fn create_member(&self, chat_id: String, user_id: i64, subscriber_id: i32) -> Result<i32> {
+        let bot_id = self.bot_id()?;
+        let mut tx = self.pool.begin().await?;
+        let member_id: Option<i32> = sqlx::query_scalar(
+            "
+                INSERT INTO subscribers (bot_id, chat_id, user_id, created_at)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT DO NOTHING
+                RETURNING id;
+            ",
+        )
+        .bind(bot_id)
+        .bind(&chat_id)
+        .bind(user_id)
+        .bind(encode_timestamp(OffsetDateTime::now_utc())?)
+        .fetch_optional(&mut tx)
+        .await?;
+        let member_id = match member_id {
+            Some(member_id) => member_id,
+            //another message of the member has created it meanwhile
+            None => {
+                tx.rollback().await?;
+                return self
+                    .get_member_id(chat_id, user_id)
+                    .await?
+                    .ok_or(anyhow!("The member is neither created nor found!"));
+            }
+        };
+        sqlx::query(
+            "
+                INSERT INTO bots_subscribers (bot_id, subscriber_id)
+                VALUES (?1, ?2);
+            ",
+        )
+        .bind(bot_id)
+        .bind(member_id)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query(
+            "
+                INSERT INTO chats_states
+                (bot_id, subscriber_id, drawn_cards, language, descriptions_format, deck_id, reversed_cards)
+                SELECT bot_id, ?1, '[]', language, descriptions_format, deck_id, reversed_cards
+                FROM chats_states WHERE subscriber_id = ?2;
+            ",
+        )
+        .bind(member_id)
+        .bind(subscriber_id)
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(member_id)
+    }
+
+    async fn get_per_member_decks(&self, subscriber_id: i32) -> Result<bool> {
+        let query_result = sqlx::query_scalar(
+            "
+            SELECT per_member_decks FROM chats_states
+            WHERE subscriber_id=?1;
+        ",
+        )
+        .bind(subscriber_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(query_result)
+    }
+
+    async fn set_per_member_decks(&self, value: bool, subscriber_id: i32) -> Result<()> {
+        let bot_id = self.bot_id()?;
+        sqlx::query(
+            "
+                UPDATE chats_states SET per_member_decks=?1
+                WHERE subscriber_id=?2 AND bot_id=?3;
+            ",
+        )
+        .bind(value)
+        .bind(subscriber_id)
+        .bind(bot_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_language(&self, subscriber_id: i32) -> Result<Language> {
+        let query_result: String = sqlx::query_scalar(
+            "
+            SELECT language FROM chats_states
+            WHERE subscriber_id=?1;
+        ",
+        )
+        .bind(subscriber_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(Language::new(&query_result))
+    }
+
+    async fn set_language(&self, language: &Language, subscriber_id: i32) -> Result<()> {
+        let bot_id = self.bot_id()?;
+        sqlx::query(
+            "
+                UPDATE chats_states SET language=?1
+                WHERE subscriber_id=?2 AND bot_id=?3;
+            ",
+        )
+        .bind(language.code())
+        .bind(subscriber_id)
+        .bind(bot_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_descriptions_format(&self, subscriber_id: i32) -> Result<i32> {
+        let query_result = sqlx::query_scalar(
+            "
+            SELECT descriptions_format FROM chats_states
+            WHERE subscriber_id=?1;
+        ",
+        )
+        .bind(subscriber_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(query_result)
+    }
+
+    async fn set_description(&self, value: i32, subscriber_id: i32) -> Result<()> {
+        let bot_id = self.bot_id()?;
+        sqlx::query(
+            "
+                UPDATE chats_states SET descriptions_format=?1
+                WHERE subscriber_id=?2 AND bot_id=?3;
+            ",
+        )
+        .bind(value)
+        .bind(subscriber_id)
+        .bind(bot_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    //the members of a group chat get the setting too
+    async fn set_reversed_cards(&self, value: bool, subscriber_id: i32) -> Result<()> {
+        let bot_id = self.bot_id()?;
+        sqlx::query(
+            "
+                UPDATE chats_states SET reversed_cards=?1
+                WHERE bot_id=?3 AND subscriber_id IN (
+                    SELECT id FROM subscribers
+                    WHERE chat_id = (SELECT chat_id FROM subscribers WHERE id = ?2)
+                );
+            ",
+        )
+        .bind(value)
+        .bind(subscriber_id)
+        .bind(bot_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_daily_card(&self, subscriber_id: i32) -> Result<(Option<Time>, i32)> {
+        let query_result: (Option<Time>, i32) = sqlx::query_as(
+            "
+            SELECT daily_card_time, daily_card_utc_offset FROM chats_states
+            WHERE subscriber_id=?1;
+        ",
+        )
+        .bind(subscriber_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(query_result)
+    }
+
+    async fn set_daily_card(&self, time: Option<Time>, utc_offset: i32, subscriber_id: i32) -> Result<()> {
+        let bot_id = self.bot_id()?;
+        sqlx::query(
+            "
+                UPDATE chats_states SET daily_card_time=?1, daily_card_utc_offset=?2
+                WHERE subscriber_id=?3 AND bot_id=?4;
+            ",
+        )
+        .bind(time)
+        .bind(utc_offset)
+        .bind(subscriber_id)
+        .bind(bot_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_daily_card_subscribers(&self) -> Result<Vec<DailyCardSubscriber>> {
+        let bot_id = self.bot_id()?;
+        let rows: Vec<(i32, String, Time, i32, Option<Date>)> = sqlx::query_as(
+            "
+                SELECT subscribers.id, subscribers.chat_id,
+                chats_states.daily_card_time,
+                chats_states.daily_card_utc_offset, chats_states.daily_card_sent_on
+                FROM chats_states
+                JOIN subscribers ON subscribers.id = chats_states.subscriber_id
+                WHERE chats_states.bot_id = ?1 AND chats_states.daily_card_time IS NOT NULL;
+            ",
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(subscriber_id, chat_id, daily_card_time, daily_card_utc_offset, daily_card_sent_on)| {
+                DailyCardSubscriber {
+                    subscriber_id,
+                    chat_id,
+                    daily_card_time,
+                    daily_card_utc_offset,
+                    daily_card_sent_on,
+                }
+            })
+            .collect())
+    }
+
+    //dates are stored as yyyy-mm-dd text, so they are compared as strings
+    async fn mark_daily_card_sent(&self, subscriber_id: i32, date: Date) -> Result<bool> {
+        let result = sqlx::query(
+            "
+                UPDATE chats_states SET daily_card_sent_on=?1
+                WHERE subscriber_id=?2
+                AND (daily_card_sent_on IS NULL OR daily_card_sent_on < ?1);
+            ",
+        )
+        .bind(date)
+        .bind(subscriber_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_decks(&self) -> Result<Vec<Deck>> {
+        sqlx::query(
+            "
+                SELECT id, name, path, with_replacement, markup FROM decks ORDER BY id;
+            ",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(deck_from_row)
+        .collect()
+    }
+
+    async fn get_deck(&self, subscriber_id: i32) -> Result<Deck> {
+        let row = sqlx::query(
+            "
+                SELECT decks.id, decks.name, decks.path, decks.with_replacement, decks.markup FROM decks
+                JOIN chats_states ON chats_states.deck_id = decks.id
+                WHERE chats_states.subscriber_id = ?1;
+            ",
+        )
+        .bind(subscriber_id)
+        .fetch_one(&self.pool)
+        .await?;
+        deck_from_row(&row)
+    }
+
+    //the members of a group chat get the deck too
+    async fn set_deck(&self, deck_id: i32, subscriber_id: i32) -> Result<()> {
+        let bot_id = self.bot_id()?;
+        sqlx::query(
+            "
+                UPDATE chats_states SET deck_id=?1
+                WHERE bot_id=?3 AND subscriber_id IN (
+                    SELECT id FROM subscribers
+                    WHERE chat_id = (SELECT chat_id FROM subscribers WHERE id = ?2)
+                );
+            ",
+        )
+        .bind(deck_id)
+        .bind(subscriber_id)
+        .bind(bot_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    //decks' paths are relative to pictures_path,
+    //the main deck in pictures_path itself is created by migrations
+    async fn check_cards_table(&self, pictures_path: &str) -> Result<()> {
+        //the drawing of every deck follows its deck.yml
+        for deck_folder in deck_files::deck_folders(pictures_path)? {
+            sqlx::query(
+                "
+                    INSERT INTO decks (name, path, with_replacement, markup)
+                    VALUES (?1, ?2, ?3, ?4)
+                    ON CONFLICT (path) DO UPDATE SET
+                    with_replacement = excluded.with_replacement, markup = excluded.markup;
+                ",
+            )
+            .bind(deck_folder.name)
+            .bind(deck_folder.path)
+            .bind(deck_folder.with_replacement)
+            .bind(deck_folder.markup)
+            .execute(&self.pool)
+            .await?;
+        }
+        let main_deck = deck_files::deck_info(Path::new(pictures_path))?;
+        sqlx::query(
+            "
+                UPDATE decks SET with_replacement = ?1, markup = ?2 WHERE path = '.';
+            ",
+        )
+        .bind(main_deck.with_replacement)
+        .bind(main_deck.markup)
+        .execute(&self.pool)
+        .await?;
+        for deck in self.get_decks().await? {
+            let count: i64 = sqlx::query_scalar(
+                "
+                    SELECT COUNT(*) FROM cards WHERE deck_id = ?1;
+                ",
+            )
+            .bind(deck.id)
+            .fetch_one(&self.pool)
+            .await?;
+            if count > 0 {
+                continue;
+            }
+            let cards = deck_files::read_cards(pictures_path, &deck.path)?;
+            let mut tx = self.pool.begin().await?;
+            for card in cards {
+                let card_id: i32 = sqlx::query_scalar(
+                    "
+                        INSERT INTO cards
+                        (filename, deck_id, weight)
+                        VALUES (?1, ?2, ?3)
+                        RETURNING id;
+                    ",
+                )
+                .bind(card.filename)
+                .bind(deck.id)
+                .bind(card.weight)
+                .fetch_one(&mut tx)
+                .await?;
+                for card_translation in card.translations {
+                    sqlx::query(
+                        "
+                            INSERT INTO card_translations
+                            (card_id, language, name, description, reversed_name, reversed_description)
+                            VALUES (?1, ?2, ?3, ?4, ?5, ?6);
+                        ",
+                    )
+                    .bind(card_id)
+                    .bind(card_translation.language)
+                    .bind(card_translation.name)
+                    .bind(card_translation.description)
+                    .bind(card_translation.reversed_name)
+                    .bind(card_translation.reversed_description)
+                    .execute(&mut tx)
+                    .await?;
+                }
+            }
+            tx.commit().await?;
+        }
+        Ok(())
+    }
+
+    async fn get_deck_cards(&self, deck_id: i32) -> Result<Vec<deck_files::CardRecord>> {
+        let rows = sqlx::query(
+            "
+                SELECT cards.id, cards.filename, cards.weight, card_translations.language, card_translations.name,
+                card_translations.description, card_translations.reversed_name,
+                card_translations.reversed_description
+                FROM cards
+                JOIN card_translations ON card_translations.card_id = cards.id
+                WHERE cards.deck_id = ?1
+                ORDER BY cards.id, card_translations.language;
+            ",
+        )
+        .bind(deck_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut cards: Vec<(i32, deck_files::CardRecord)> = vec![];
+        for row in rows {
+            let card_id: i32 = row.try_get("id")?;
+            let translation = deck_files::CardTranslation {
+                language: row.try_get("language")?,
+                name: row.try_get("name")?,
+                description: row.try_get("description")?,
+                reversed_name: row.try_get("reversed_name")?,
+                reversed_description: row.try_get("reversed_description")?,
+            };
+            match cards.last_mut() {
+                Some((id, card)) if *id == card_id => card.translations.push(translation),
+                _ => cards.push((
+                    card_id,
+                    deck_files::CardRecord {
+                        filename: row.try_get("filename")?,
+                        weight: row.try_get("weight")?,
+                        translations: vec![translation],
+                    },
+                )),
+            }
+        }
+        Ok(cards.into_iter().map(|(_, card)| card).collect())
+    }
+
+    async fn import_deck(
+        &self,
+        name: &str,
+        path: &str,
+        with_replacement: bool,
+        markup: markup::Format,
+        cards: &[deck_files::CardRecord],
+        changed_images: &[(String, String)],
+    ) -> Result<i32> {
+        let mut tx = self.pool.begin().await?;
+        let deck_id: i32 = sqlx::query_scalar(
+            "
+                INSERT INTO decks (name, path, with_replacement, markup)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (path) DO UPDATE SET
+                name = excluded.name, with_replacement = excluded.with_replacement, markup = excluded.markup
+                RETURNING id;
+            ",
+        )
+        .bind(name)
+        .bind(path)
+        .bind(with_replacement)
+        .bind(markup)
+        .fetch_one(&mut tx)
+        .await?;
+        for card in cards {
+            let card_id: i32 = sqlx::query_scalar(
+                "
+                    INSERT INTO cards (filename, deck_id, weight)
+                    VALUES (?1, ?2, ?3)
+                    ON CONFLICT (deck_id, filename) DO UPDATE SET weight = excluded.weight
+                    RETURNING id;
+                ",
+            )
+            .bind(&card.filename)
+            .bind(deck_id)
+            .bind(card.weight)
+            .fetch_one(&mut tx)
+            .await?;
+            for card_translation in &card.translations {
+                sqlx::query(
+                    "
+                        INSERT INTO card_translations
+                        (card_id, language, name, description, reversed_name, reversed_description)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                        ON CONFLICT (card_id, language) DO UPDATE SET
+                        name = excluded.name, description = excluded.description,
+                        reversed_name = excluded.reversed_name,
+                        reversed_description = excluded.reversed_description;
+                    ",
+                )
+                .bind(card_id)
+                .bind(&card_translation.language)
+                .bind(&card_translation.name)
+                .bind(&card_translation.description)
+                .bind(&card_translation.reversed_name)
+                .bind(&card_translation.reversed_description)
+                .execute(&mut tx)
+                .await?;
+            }
+        }
+        for (filename, language) in changed_images {
+            sqlx::query(
+                "
+                    DELETE FROM file_ids
+                    WHERE language = ?1 AND card_id IN (
+                        SELECT id FROM cards WHERE deck_id = ?2 AND filename = ?3
+                    );
+                ",
+            )
+            .bind(language)
+            .bind(deck_id)
+            .bind(filename)
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(deck_id)
+    }
+
+    async fn draw_cards(
+        &self,
+        count: usize,
+        spread_id: Option<&str>,
+        language: &Language,
+        subscriber_id: i32,
+    ) -> Result<Option<(Deck, Vec<(Card, bool)>)>> {
+        let mut tx = self.pool.begin().await?;
+        //writing first takes the database lock, like SELECT ... FOR UPDATE,
+        //so parallel draws don't get the same cards
+        sqlx::query(
+            "
+                UPDATE chats_states SET drawn_cards = drawn_cards
+                WHERE subscriber_id = ?1;
+            ",
+        )
+        .bind(subscriber_id)
+        .execute(&mut tx)
+        .await?;
+        let (drawn_cards, deck_id, reversed_cards): (String, i32, bool) = sqlx::query_as(
+            "
+                SELECT drawn_cards, deck_id, reversed_cards
+                FROM chats_states WHERE subscriber_id = ?1;
+            ",
+        )
+        .bind(subscriber_id)
+        .fetch_one(&mut tx)
+        .await?;
+        let mut skip_cards_ids = decode_drawn_cards(&drawn_cards)?;
+
+        let row = sqlx::query(
+            "
+                SELECT id, name, path, with_replacement, markup FROM decks WHERE id = ?1;
+            ",
+        )
+        .bind(deck_id)
+        .fetch_one(&mut tx)
+        .await?;
+        let deck = deck_from_row(&row)?;
+
+        let cards = sqlx::query(&format!(
+            "
+                {}
+                WHERE cards.deck_id = ?1 AND card_translations.language = ?2;
+            ",
+            SELECT_CARDS
+        ))
+        .bind(deck.id)
+        .bind(language.code())
+        .bind(self.bot_id()?)
+        .fetch_all(&mut tx)
+        .await?
+        .iter()
+        .map(card_from_row)
+        .collect::<Result<Vec<Card>>>()?;
+
+        let cards: Vec<Card> = cards
+            .into_iter()
+            .filter(|c| deck.with_replacement || !skip_cards_ids.contains(&c.id))
+            .collect();
+        let chosen_cards = match process::choose_cards(cards, count) {
+            Some(chosen_cards) => chosen_cards,
+            None => return Ok(None),
+        };
+
+        //decks with replacement don't put the drawn cards aside
+        if !deck.with_replacement {
+            skip_cards_ids.extend(chosen_cards.iter().map(|c| c.id));
+            sqlx::query(
+                "
+                    UPDATE chats_states
+                    SET drawn_cards=?1
+                    WHERE subscriber_id = ?2;
+                ",
+            )
+            .bind(serde_json::to_string(&skip_cards_ids)?)
+            .bind(subscriber_id)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        let chosen_cards: Vec<(Card, bool)> = chosen_cards
+            .into_iter()
+            .map(|card| {
+                let reversed = reversed_cards && rand::random::<bool>();
+                (card, reversed)
+            })
+            .collect();
+        let drawn_at = encode_timestamp(OffsetDateTime::now_utc())?;
+        for (position, (card, reversed)) in chosen_cards.iter().enumerate() {
+            let spread_position = spread_id.map(|_| position as i32);
+            sqlx::query(
+                "
+                    INSERT INTO draws
+                    (subscriber_id, card_id, deck_id, reversed, spread, spread_position, drawn_at)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);
+                ",
+            )
+            .bind(subscriber_id)
+            .bind(card.id)
+            .bind(deck.id)
+            .bind(reversed)
+            .bind(spread_id)
+            .bind(spread_position)
+            .bind(&drawn_at)
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(Some((deck, chosen_cards)))
+    }
+
+    //only the cards of the selected deck are shuffled back,
+    //drawn cards of the other decks stay drawn
+    async fn shuffle_cards_back(&self, subscriber_id: i32) -> Result<()> {
+        sqlx::query(
+            "
+            UPDATE chats_states
+            SET drawn_cards = (
+                SELECT json_group_array(value) FROM json_each(chats_states.drawn_cards)
+                WHERE value NOT IN (SELECT id FROM cards WHERE deck_id = chats_states.deck_id)
+            )
+            WHERE subscriber_id = ?1;
+        ",
+        )
+        .bind(subscriber_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn set_file_id(
+        &self,
+        card_id: i32,
+        language: &Language,
+        reversed: bool,
+        id: String,
+        content_hash: String,
+    ) -> Result<()> {
+        let bot_id = self.bot_id()?;
+        sqlx::query(
+            "
+                INSERT INTO file_ids (bot_id, card_id, language, reversed, file_id, content_hash)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                ON CONFLICT (bot_id, card_id, language, reversed) DO UPDATE SET
+                file_id = excluded.file_id, content_hash = excluded.content_hash;
+            ",
+        )
+        .bind(bot_id)
+        .bind(card_id)
+        .bind(language.code())
+        .bind(reversed)
+        .bind(id)
+        .bind(content_hash)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_file_ids(&self) -> Result<Vec<FileId>> {
+        let bot_id = self.bot_id()?;
+        let rows = sqlx::query(
+            "
+                SELECT file_ids.card_id, file_ids.language, file_ids.reversed,
+                file_ids.content_hash, cards.filename, decks.path
+                FROM file_ids
+                JOIN cards ON cards.id = file_ids.card_id
+                JOIN decks ON decks.id = cards.deck_id
+                WHERE file_ids.bot_id = ?1
+                ORDER BY file_ids.card_id;
+            ",
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                let language: String = row.try_get("language")?;
+                let path: String = row.try_get("path")?;
+                let filename: String = row.try_get("filename")?;
+                Ok(FileId {
+                    card_id: row.try_get("card_id")?,
+                    image_path: file_ids::image_path(&path, &language, &filename),
+                    language: Language::new(&language),
+                    reversed: row.try_get("reversed")?,
+                    content_hash: row.try_get("content_hash")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn delete_file_id(&self, card_id: i32, language: &Language, reversed: bool) -> Result<()> {
+        let bot_id = self.bot_id()?;
+        sqlx::query(
+            "
+                DELETE FROM file_ids
+                WHERE bot_id = ?1 AND card_id = ?2 AND language = ?3 AND reversed = ?4;
+            ",
+        )
+        .bind(bot_id)
+        .bind(card_id)
+        .bind(language.code())
+        .bind(reversed)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_reversed_cards_decks(&self) -> Result<Vec<i32>> {
+        let bot_id = self.bot_id()?;
+        let deck_ids: Vec<i32> = sqlx::query_scalar(
+            "
+                SELECT DISTINCT deck_id FROM chats_states
+                WHERE bot_id = ?1 AND reversed_cards
+                ORDER BY deck_id;
+            ",
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(deck_ids)
+    }
+
+    async fn search_cards(&self, text: &str, language: &Language, deck_id: i32) -> Result<Vec<Card>> {
+        let text = text.to_lowercase();
+        let cards = sqlx::query(&format!(
+            "
+                {}
+                WHERE cards.deck_id = ?1 AND card_translations.language = ?2
+                ORDER BY card_translations.name;
+            ",
+            SELECT_CARDS
+        ))
+        .bind(deck_id)
+        .bind(language.code())
+        .bind(self.bot_id()?)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(card_from_row)
+        .collect::<Result<Vec<Card>>>()?;
+        Ok(cards
+            .into_iter()
+            .filter(|card| card.name.to_lowercase().contains(&text))
+            .collect())
+    }
+
+    async fn count_draws(&self, subscriber_id: i32) -> Result<i64> {
+        let count = sqlx::query_scalar(
+            "
+                SELECT COUNT(*) FROM draws WHERE subscriber_id = ?1;
+            ",
+        )
+        .bind(subscriber_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count)
+    }
+
+    //the most recent draws first, cards without a translation to the language are shown by file names
+    async fn get_draws(&self, language: &Language, offset: i64, limit: i64, subscriber_id: i32) -> Result<Vec<Draw>> {
+        let rows: Vec<(i32, String, bool, String)> = sqlx::query_as(
+            "
+                SELECT draws.id, COALESCE(card_translations.name, cards.filename),
+                draws.reversed, draws.drawn_at
+                FROM draws
+                JOIN cards ON cards.id = draws.card_id
+                LEFT JOIN card_translations ON card_translations.card_id = draws.card_id
+                AND card_translations.language = ?2
+                WHERE draws.subscriber_id = ?1
+                ORDER BY draws.drawn_at DESC, draws.id DESC
+                LIMIT ?4 OFFSET ?3;
+            ",
+        )
+        .bind(subscriber_id)
+        .bind(language.code())
+        .bind(offset)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|(id, name, reversed, drawn_at)| {
+                Ok(Draw {
+                    id,
+                    name,
+                    reversed,
+                    drawn_at: decode_timestamp(&drawn_at)?,
+                })
+            })
+            .collect()
+    }
+
+    //the card of a draw from history, it's not drawn again
+    async fn drawn_card(
+        &self,
+        draw_id: i32,
+        language: &Language,
+        descriptions: i32,
+        subscriber_id: i32,
+    ) -> Result<Option<CardData>> {
+        let draw: Option<(i32, i32, bool)> = sqlx::query_as(
+            "
+                SELECT card_id, deck_id, reversed FROM draws
+                WHERE id = ?1 AND subscriber_id = ?2;
+            ",
+        )
+        .bind(draw_id)
+        .bind(subscriber_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let (card_id, deck_id, reversed) = match draw {
+            Some(draw) => draw,
+            None => return Ok(None),
+        };
+        let row = sqlx::query(
+            "
+                SELECT id, name, path, with_replacement, markup FROM decks WHERE id = ?1;
+            ",
+        )
+        .bind(deck_id)
+        .fetch_one(&self.pool)
+        .await?;
+        let deck = deck_from_row(&row)?;
+        let row = sqlx::query(&format!(
+            "
+                {}
+                WHERE cards.id = ?1 AND card_translations.language = ?2;
+            ",
+            SELECT_CARDS
+        ))
+        .bind(card_id)
+        .bind(language.code())
+        .bind(self.bot_id()?)
+        .fetch_optional(&self.pool)
+        .await?;
+        match row {
+            Some(row) => {
+                let card = card_from_row(&row)?;
+                Ok(Some(process::card_data(&deck, &card, reversed, language, descriptions)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    //counts of chats for the bot, members of groups are not counted,
+    //"today" starts at midnight UTC
+    async fn get_stats(&self) -> Result<Stats> {
+        let bot_id = self.bot_id()?;
+        let today = today()?;
+        let (subscribers, new_subscribers, daily_card_subscribers): (i64, i64, i64) = sqlx::query_as(
+            "
+                SELECT COUNT(*),
+                COUNT(*) FILTER (WHERE subscribers.created_at >= ?2),
+                COUNT(*) FILTER (WHERE chats_states.daily_card_time IS NOT NULL)
+                FROM chats_states
+                JOIN subscribers ON subscribers.id = chats_states.subscriber_id
+                WHERE chats_states.bot_id = ?1 AND subscribers.user_id IS NULL;
+            ",
+        )
+        .bind(bot_id)
+        .bind(&today)
+        .fetch_one(&self.pool)
+        .await?;
+        let draws_today = sqlx::query_scalar(
+            "
+                SELECT COUNT(*) FROM draws
+                JOIN chats_states ON chats_states.subscriber_id = draws.subscriber_id
+                WHERE chats_states.bot_id = ?1 AND draws.drawn_at >= ?2;
+            ",
+        )
+        .bind(bot_id)
+        .bind(&today)
+        .fetch_one(&self.pool)
+        .await?;
+        let languages = sqlx::query_as(
+            "
+                SELECT language, COUNT(*) FROM chats_states
+                JOIN subscribers ON subscribers.id = chats_states.subscriber_id
+                WHERE chats_states.bot_id = ?1 AND subscribers.user_id IS NULL
+                GROUP BY language
+                ORDER BY 2 DESC, language;
+            ",
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(Stats {
+            subscribers,
+            new_subscribers,
+            daily_card_subscribers,
+            draws_today,
+            languages,
+        })
+    }
+
+    //chat ids of all chats of the active bot
+    async fn get_subscribers_chat_ids(&self) -> Result<Vec<String>> {
+        let bot_id = self.bot_id()?;
+        let chat_ids = sqlx::query_scalar(
+            "
+                SELECT subscribers.chat_id FROM subscribers
+                JOIN bots_subscribers ON bots_subscribers.subscriber_id = subscribers.id
+                WHERE bots_subscribers.bot_id = ?1 AND subscribers.user_id IS NULL
+                ORDER BY subscribers.id;
+            ",
+        )
+        .bind(bot_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(chat_ids)
+    }
+
+    async fn get_chat_state(&self, chat_id: String) -> Result<Option<ChatState>> {
+        let bot_id = self.bot_id()?;
+        let row = sqlx::query(
+            "
+                SELECT subscribers.id AS subscriber_id, subscribers.created_at,
+                chats_states.language, chats_states.descriptions_format,
+                chats_states.reversed_cards, decks.name AS deck_name,
+                json_array_length(chats_states.drawn_cards) AS drawn_cards,
+                chats_states.daily_card_time, chats_states.daily_card_utc_offset,
+                (SELECT COUNT(*) FROM draws WHERE draws.subscriber_id = subscribers.id) AS draws
+                FROM subscribers
+                JOIN chats_states ON chats_states.subscriber_id = subscribers.id
+                JOIN decks ON decks.id = chats_states.deck_id
+                WHERE subscribers.chat_id = ?1 AND subscribers.user_id IS NULL
+                AND chats_states.bot_id = ?2;
+            ",
+        )
+        .bind(chat_id)
+        .bind(bot_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let created_at: String = row.try_get("created_at")?;
+        Ok(Some(ChatState {
+            subscriber_id: row.try_get("subscriber_id")?,
+            created_at: decode_timestamp(&created_at)?,
+            language: row.try_get("language")?,
+            descriptions_format: row.try_get("descriptions_format")?,
+            reversed_cards: row.try_get("reversed_cards")?,
+            deck_name: row.try_get("deck_name")?,
+            drawn_cards: row.try_get("drawn_cards")?,
+            daily_card_time: row.try_get("daily_card_time")?,
+            daily_card_utc_offset: row.try_get("daily_card_utc_offset")?,
+            draws: row.try_get("draws")?,
+        }))
+    }
+}
@@ -0,0 +1,175 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use time::{Date, Time};
+use url::Url;
+
+use crate::admin::{ChatState, Stats};
+use crate::config::Config;
+use crate::deck_files::CardRecord;
+use crate::file_ids::FileId;
+use crate::markup;
+use crate::memory_storage::MemoryStorage;
+use crate::postgres_storage::PostgresStorage;
+use crate::sqlite_storage::SqliteStorage;
+use crate::process::{self, Card, CardData, DailyCardSubscriber, Deck, Draw, Language};
+
+//a row of the bots table, its key is the telegram token
+pub struct BotInfo {
+    pub id: i32,
+    pub name: String,
+    pub key: String,
+}
+
+//the key of the bot created by migrations, such bots use bot_token from the config
+pub const CONFIG_TOKEN_KEY: &str = "bot_token";
+
+impl BotInfo {
+    pub fn token<'a>(&'a self, config: &'a Config) -> &'a str {
+        if self.key == CONFIG_TOKEN_KEY {
+            &config.bot_token
+        } else {
+            &self.key
+        }
+    }
+}
+
+//everything the bot keeps about chats, decks and cards,
+//chats, their settings and file ids are of the bot the storage is bound to with with_bot
+#[async_trait]
+pub trait Storage: Send + Sync {
+    //the bot the storage is bound to, decks and cards are shared by all bots
+    fn bot_id(&self) -> Result<i32>;
+    //the storage for the same database bound to the bot
+    fn with_bot(&self, bot_id: i32) -> Arc<dyn Storage>;
+    async fn get_active_bots(&self) -> Result<Vec<BotInfo>>;
+    async fn get_subscriber_id(&self, chat_id: String) -> Result<Option<i32>>;
+    async fn create_subscriber(&self, chat_id: String, language: &Language) -> Result<i32>;
+    //members of group chats with per member decks have their own rows
+    async fn get_member_id(&self, chat_id: String, user_id: i64) -> Result<Option<i32>>;
+    async fn create_member(&self, chat_id: String, user_id: i64, subscriber_id: i32) -> Result<i32>;
+    async fn get_per_member_decks(&self, subscriber_id: i32) -> Result<bool>;
+    async fn set_per_member_decks(&self, value: bool, subscriber_id: i32) -> Result<()>;
+
+    async fn get_language(&self, subscriber_id: i32) -> Result<Language>;
+    async fn set_language(&self, language: &Language, subscriber_id: i32) -> Result<()>;
+    async fn get_descriptions_format(&self, subscriber_id: i32) -> Result<i32>;
+    async fn set_description(&self, value: i32, subscriber_id: i32) -> Result<()>;
+    async fn set_reversed_cards(&self, value: bool, subscriber_id: i32) -> Result<()>;
+    async fn get_daily_card(&self, subscriber_id: i32) -> Result<(Option<Time>, i32)>;
+    async fn set_daily_card(&self, time: Option<Time>, utc_offset: i32, subscriber_id: i32) -> Result<()>;
+    async fn get_daily_card_subscribers(&self) -> Result<Vec<DailyCardSubscriber>>;
+    async fn mark_daily_card_sent(&self, subscriber_id: i32, date: Date) -> Result<bool>;
+
+    async fn get_decks(&self) -> Result<Vec<Deck>>;
+    async fn get_deck(&self, subscriber_id: i32) -> Result<Deck>;
+    async fn set_deck(&self, deck_id: i32, subscriber_id: i32) -> Result<()>;
+    //fills decks and cards from pictures_path, if they are not there yet
+    async fn check_cards_table(&self, pictures_path: &str) -> Result<()>;
+    //all cards of the deck with all their translations, ordered by id
+    async fn get_deck_cards(&self, deck_id: i32) -> Result<Vec<CardRecord>>;
+    //creates the deck with the path or updates its name, drawing and markup, then adds or updates its cards
+    //by file names, the file ids of the changed images, given as (file name, language), are forgotten
+    async fn import_deck(
+        &self,
+        name: &str,
+        path: &str,
+        with_replacement: bool,
+        markup: markup::Format,
+        cards: &[CardRecord],
+        changed_images: &[(String, String)],
+    ) -> Result<i32>;
+
+    async fn draw_cards(
+        &self,
+        count: usize,
+        spread_id: Option<&str>,
+        language: &Language,
+        subscriber_id: i32,
+    ) -> Result<Option<(Deck, Vec<(Card, bool)>)>>;
+    async fn shuffle_cards_back(&self, subscriber_id: i32) -> Result<()>;
+    //file ids are of the active bot, content_hash is of the uploaded image file
+    async fn set_file_id(
+        &self,
+        card_id: i32,
+        language: &Language,
+        reversed: bool,
+        id: String,
+        content_hash: String,
+    ) -> Result<()>;
+    async fn get_file_ids(&self) -> Result<Vec<FileId>>;
+    async fn delete_file_id(&self, card_id: i32, language: &Language, reversed: bool) -> Result<()>;
+    //ids of the decks which some chat of the bot draws with reversed cards
+    async fn get_reversed_cards_decks(&self) -> Result<Vec<i32>>;
+    //cards of the deck with the text in their names, ignoring case, ordered by name
+    async fn search_cards(&self, text: &str, language: &Language, deck_id: i32) -> Result<Vec<Card>>;
+
+    async fn count_draws(&self, subscriber_id: i32) -> Result<i64>;
+    async fn get_draws(&self, language: &Language, offset: i64, limit: i64, subscriber_id: i32) -> Result<Vec<Draw>>;
+    async fn drawn_card(
+        &self,
+        draw_id: i32,
+        language: &Language,
+        descriptions: i32,
+        subscriber_id: i32,
+    ) -> Result<Option<CardData>>;
+
+    async fn get_stats(&self) -> Result<Stats>;
+    async fn get_subscribers_chat_ids(&self) -> Result<Vec<String>>;
+    async fn get_chat_state(&self, chat_id: String) -> Result<Option<ChatState>>;
+
+    async fn random_card_file_name(
+        &self,
+        language: &Language,
+        descriptions: i32,
+        subscriber_id: i32,
+    ) -> Result<Option<CardData>> {
+        let cards = self.draw_cards(1, None, language, subscriber_id).await?;
+        cards
+            .and_then(|(deck, cards)| {
+                cards
+                    .first()
+                    .map(|(card, reversed)| process::card_data(&deck, card, *reversed, language, descriptions))
+            })
+            .transpose()
+    }
+
+    async fn random_cards(
+        &self,
+        count: usize,
+        spread_id: &str,
+        language: &Language,
+        descriptions: i32,
+        subscriber_id: i32,
+    ) -> Result<Option<Vec<CardData>>> {
+        let cards = self.draw_cards(count, Some(spread_id), language, subscriber_id).await?;
+        cards
+            .map(|(deck, cards)| {
+                cards
+                    .iter()
+                    .map(|(card, reversed)| process::card_data(&deck, card, *reversed, language, descriptions))
+                    .collect()
+            })
+            .transpose()
+    }
+}
+
+//the storage is chosen by the scheme of database_path,
+//sqlite: is a single file, memory: keeps everything in memory till restart
+pub async fn storage(config: &Config) -> Result<Arc<dyn Storage>> {
+    let url = Url::parse(&config.database_path)?;
+    let storage: Arc<dyn Storage> = match url.scheme() {
+        "postgres" | "postgresql" => Arc::new(
+            PostgresStorage::new(config.database_path.clone(), config.database_max_connections).await?,
+        ),
+        "sqlite" => Arc::new(
+            SqliteStorage::new(&config.database_path, config.database_max_connections).await?,
+        ),
+        "memory" => Arc::new(MemoryStorage::new()),
+        other => return Err(anyhow!("Unsupported database {}!", other)),
+    };
+    Ok(storage)
+}
+
+pub const SCHEMES: [&str; 4] = ["postgres", "postgresql", "sqlite", "memory"];
@@ -1,15 +1,24 @@
 use std::sync::Arc;
 
-use anyhow::{Error, Result};
-use sqlx::postgres::PgPool;
+use anyhow::Result;
 use teloxide::{
     prelude::*,
-    types::{Chat, Me, MediaKind, MessageId, MessageKind, MessageCommon},
+    types::{
+        Chat, InlineQueryResult, InlineQueryResultCachedPhoto, InputMedia, InputMediaPhoto, Me, MediaKind,
+        MessageId, MessageKind, MessageCommon, ParseMode, User,
+    },
     utils::command::BotCommands,
 };
 
-use crate::database_interactions;
+use crate::admin;
+use crate::callbacks::Callback;
+use crate::config::Config;
+use crate::error::Error;
+use crate::markup;
 use crate::process;
+use crate::reloadable::Reloadable;
+use crate::spreads;
+use crate::storage::Storage;
 use crate::translations;
 
 #[derive(BotCommands, Clone)]
@@ -30,14 +39,24 @@ pub enum Command {
     About,
     #[command(description = "Send me a random card.")]
     Card,
+    #[command(description = "Choose a spread or draw the spread with the given id.")]
+    Spread(String),
     #[command(description = "Shuffle the drawn cards back into deck.")]
     Shuffle,
-    #[command(description = "Choose language.")]
-    Language,
-    #[command(description = "Set English language.")]
-    En,
-    #[command(description = "Set Russian language.")]
-    Ru,
+    #[command(description = "Show the history of drawn cards.")]
+    History(String),
+    #[command(description = "off")]
+    HistoryEntry(String),
+    #[command(description = "Choose language or set the language with the given code.")]
+    Language(String),
+    #[command(description = "Choose a deck or select the deck with the given id.")]
+    Decks(String),
+    #[command(description = "Browse the cards of the deck.")]
+    Deck(String),
+    #[command(description = "off")]
+    DeckCard(String),
+    #[command(description = "Find cards by name, e.g. /find shiva.")]
+    Find(String),
     #[command(description = "Choose description settings.")]
     Description,
     #[command(description = "Set full description.")]
@@ -46,169 +65,454 @@ pub enum Command {
     NamesOnly,
     #[command(description = "No descriptions.")]
     NoDescription,
+    #[command(description = "Card of the day settings, e.g. /dailycard 09:00 +03:00 or /dailycard off.")]
+    DailyCard(String),
+    #[command(description = "Choose reversed cards settings.")]
+    Reversed,
+    #[command(description = "Cards may be drawn reversed.")]
+    ReversedOn,
+    #[command(description = "Cards are drawn upright only.")]
+    ReversedOff,
+    #[command(description = "Choose whether members of a group share the deck.")]
+    GroupDecks,
+    #[command(description = "Members of the group draw from the shared deck.")]
+    SharedDeck,
+    #[command(description = "Every member of the group draws from their own deck.")]
+    MemberDecks,
+}
+
+impl Command {
+    //in group chats these are accepted only from administrators
+    pub fn is_group_setting(&self) -> bool {
+        matches!(
+            self,
+            Command::Settings
+                | Command::Language(_)
+                | Command::Decks(_)
+                | Command::Description
+                | Command::FullDescription
+                | Command::NamesOnly
+                | Command::NoDescription
+                | Command::DailyCard(_)
+                | Command::Reversed
+                | Command::ReversedOn
+                | Command::ReversedOff
+                | Command::GroupDecks
+                | Command::SharedDeck
+                | Command::MemberDecks
+        )
+    }
+}
+
+//accepted only from the chats in admin_chat_ids, not shown in the commands list
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase", description = "Admin commands:")]
+pub enum AdminCommand {
+    #[command(description = "Subscribers, draws today and languages.")]
+    Stats,
+    #[command(description = "Reload translations, spreads and decks.")]
+    Reload,
+    #[command(description = "Send the text to all subscribers.")]
+    Broadcast(String),
+    #[command(description = "Show the settings of the chat with the given id.")]
+    Whois(String),
+    #[command(description = "Upload the card images without file ids to the storage chat.")]
+    Warmup,
 }
 
 pub async fn message_handler(
     bot: Bot,
     msg: Message,
     me: Me,
-    pool: PgPool,
-    translation: Arc<translations::Translation>,
+    storage: Arc<dyn Storage>,
+    translation: Arc<Reloadable<translations::Translation>>,
+    spreads: Arc<Reloadable<spreads::Spreads>>,
+    config: Arc<Config>,
 ) -> Result<()> {
     if let Some(text) = msg.text() {
-        match BotCommands::parse(text, me.username()) {
-            Ok(command) => {
-                process(command, msg.chat, msg.id, bot, None, &pool, &translation).await?;
+        //in groups the bot sees commands meant for other bots, so it answers only /command@bot_name
+        if !msg.chat.is_private() && !is_addressed(text, me.username()) {
+            return Ok(());
+        }
+        if admin::is_admin(msg.chat.id, &config) {
+            if let Ok(command) = AdminCommand::parse(text, me.username()) {
+                admin::process_admin_command(command, msg.chat.id, bot, &*storage, &translation, &spreads, &config).await?;
+                return Ok(());
+            }
+        }
+        let (translation, spreads) = (translation.get(), spreads.get());
+        //commands like /en are generated from the languages found on start
+        let command = BotCommands::parse(text, me.username())
+            .ok()
+            .or_else(|| language_command(text, me.username(), &translation).map(Command::Language));
+        match command {
+            Some(command) => {
+                //anonymous administrators send messages on behalf of the group
+                let anonymous_admin = msg.sender_chat().map(|chat| chat.id) == Some(msg.chat.id);
+                let sender = msg.from().cloned();
+                process(command, msg.chat, msg.id, sender.as_ref(), anonymous_admin, bot, None, &*storage, &translation, &spreads, &config).await?;
             }
-            Err(_) => {
-                send_error_message("command_not_found", msg.chat, bot, &pool, &translation).await?;
+            None => {
+                send_error_message("command_not_found", msg.chat, bot, &*storage, &translation).await?;
             }
         }
-    } else {
-        send_error_message("text_expected", msg.chat, bot, &pool, &translation).await?;
+    } else if msg.chat.is_private() {
+        send_error_message("text_expected", msg.chat, bot, &*storage, &translation.get()).await?;
     }
     Ok(())
 }
 
+//the text is a command with this bot's name like /command@bot_name
+pub(crate) fn is_addressed(text: &str, bot_name: &str) -> bool {
+    text.trim()
+        .strip_prefix('/')
+        .and_then(|command| command.split_whitespace().next())
+        .and_then(|command| command.split_once('@'))
+        .is_some_and(|(_, name)| name.eq_ignore_ascii_case(bot_name))
+}
+
 pub async fn callback_handler(
     bot: Bot,
     q: CallbackQuery,
-    pool: PgPool,
-    translation: Arc<translations::Translation>,
+    me: Me,
+    storage: Arc<dyn Storage>,
+    translation: Arc<Reloadable<translations::Translation>>,
+    spreads: Arc<Reloadable<spreads::Spreads>>,
+    config: Arc<Config>,
 ) -> Result<()> {
+    let (translation, spreads) = (translation.get(), spreads.get());
+    //in groups other bots' keyboards are not ours to answer
+    if q.message.as_ref().and_then(|message| message.from()).map(|user| user.id) != Some(me.id) {
+        return Ok(());
+    }
     if let Some(request) = q.data.clone() {
-        let command: Command = match request.as_str() {
-            translations::ABOUT_EN | translations::ABOUT_RU => Command::About,
-            translations::MAIN_MENU_EN | translations::MAIN_MENU_RU => Command::MainMenu,
-            translations::CARD_EN | translations::CARD_RU => Command::Card,
-            translations::SHUFFLE_EN | translations::SHUFFLE_RU => Command::Shuffle,
-            translations::SETTINGS_EN | translations::SETTINGS_RU => Command::Settings,
-            translations::LANGUAGE_EN | translations::LANGUAGE_RU => Command::Language,
-            translations::DESCRIPTIONS_EN | translations::DESCRIPTIONS_RU => Command::Description,
-            translations::EN_EN => Command::En,
-            translations::RU_RU => Command::Ru,
-            translations::FULL_DESCRIPTIONS_EN | translations::FULL_DESCRIPTIONS_RU => {
-                Command::FullDescription
-            }
-            translations::NAMES_ONLY_EN | translations::NAMES_ONLY_RU => Command::NamesOnly,
-            translations::NO_DESCRIPTIONS_EN | translations::NO_DESCRIPTIONS_RU => {
-                Command::NoDescription
-            }
-            _other => {
-                if let Some(Message { chat, .. }) = q.message.clone() {
-                    send_error_message("command_not_found", chat, bot, &pool, &translation).await?;
+        let command = match Callback::parse(&request) {
+            Some(callback) => callback.command(),
+            None => match legacy_command(&request, &translation) {
+                Some(command) => command,
+                None => {
+                    if let Some(Message { chat, .. }) = q.message.clone() {
+                        send_error_message("command_not_found", chat, bot, &*storage, &translation).await?;
+                    }
+                    return Ok(());
                 }
-                return Ok(());
-            }
+            },
         };
 
         if let Some(Message { id, chat, .. }) = q.message.clone() {
-            process(command, chat, id, bot, Some(q), &pool, &translation).await?;
+            let sender = q.from.clone();
+            process(command, chat, id, Some(&sender), false, bot, Some(q), &*storage, &translation, &spreads, &config).await?;
         }
     }
     Ok(())
 }
 
+//results are personal, as they depend on the user's language and deck
+pub async fn inline_query_handler(
+    bot: Bot,
+    q: InlineQuery,
+    storage: Arc<dyn Storage>,
+    translation: Arc<Reloadable<translations::Translation>>,
+) -> Result<()> {
+    let translation = translation.get();
+    let cards = process::inline_cards(
+        &q.query,
+        q.from.id.to_string(),
+        q.from.language_code.as_deref(),
+        &*storage,
+        &translation,
+    )
+    .await?;
+    let results: Vec<InlineQueryResult> = cards
+        .into_iter()
+        .filter_map(|card| {
+            let mut photo = InlineQueryResultCachedPhoto::new(card.id.to_string(), card.file_id?);
+            //inline results have no follow-up messages for long texts
+            if markup::fits_caption(&card.message_text) {
+                photo = photo.caption(card.message_text).parse_mode(ParseMode::Html);
+            }
+            Some(InlineQueryResult::CachedPhoto(photo))
+        })
+        .collect();
+    bot.answer_inline_query(q.id, results)
+        .is_personal(true)
+        .cache_time(0)
+        .await?;
+    Ok(())
+}
+
+//keyboards sent before callbacks became typed use buttons' texts as callback data,
+//they are still accepted while such messages may be around
+fn legacy_command(request: &str, translation: &translations::Translation) -> Option<Command> {
+    if let Some(spread_id) = request.strip_prefix("spread ") {
+        return Some(Command::Spread(spread_id.to_string()));
+    }
+    if let Some(deck_id) = request.strip_prefix("deck ") {
+        return Some(Command::Decks(deck_id.to_string()));
+    }
+    let command = match translation.button_key(request) {
+        Some(translations::ABOUT) => Command::About,
+        Some(translations::MAIN_MENU) => Command::MainMenu,
+        Some(translations::CARD) => Command::Card,
+        Some(translations::SPREADS) => Command::Spread(String::new()),
+        Some(translations::SHUFFLE) => Command::Shuffle,
+        Some(translations::SETTINGS) => Command::Settings,
+        Some(translations::LANGUAGE) => Command::Language(String::new()),
+        Some(translations::DESCRIPTIONS) => Command::Description,
+        Some(translations::DECK) => Command::Decks(String::new()),
+        Some(translations::FULL_DESCRIPTIONS) => Command::FullDescription,
+        Some(translations::NAMES_ONLY) => Command::NamesOnly,
+        Some(translations::NO_DESCRIPTIONS) => Command::NoDescription,
+        Some(translations::REVERSED) => Command::Reversed,
+        Some(translations::REVERSED_ON) => Command::ReversedOn,
+        Some(translations::REVERSED_OFF) => Command::ReversedOff,
+        _ => Command::Language(translation.language_by_name(request)?.code().to_string()),
+    };
+    Some(command)
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn process(
     command: Command,
     chat: Chat,
     message_id: MessageId,
+    sender: Option<&User>,
+    anonymous_admin: bool,
     bot: Bot,
     q: Option<CallbackQuery>,
-    pool: &PgPool,
+    storage: &dyn Storage,
     translation: &translations::Translation,
+    spreads: &spreads::Spreads,
+    config: &Config,
 ) -> Result<()> {
-    let action =
-        process::process_message(command, chat.id.to_string(), pool, translation).await;
+    let user = match chat_user(&command, &chat, sender, anonymous_admin, &bot).await {
+        Ok(user) => user,
+        Err(e) => {
+            _ = log_error(chat, bot, e, storage, translation).await;
+            return Ok(());
+        }
+    };
+    let action = process::process_message(
+        command,
+        &user,
+        storage,
+        translation,
+        spreads,
+        config,
+    )
+    .await;
     match action {
         Ok(action_inner) => {
             let result =
-                process_action(action_inner, chat.clone(), message_id, bot.clone(), q, pool).await;
+                process_action(action_inner, chat.id, Some(message_id), bot.clone(), q, storage, config).await;
             if let Err(e) = result {
-                _ = log_error(chat, bot, e, pool, translation).await;
+                _ = log_error(chat, bot, e, storage, translation).await;
             }
         }
         Err(e) => {
-            _ = log_error(chat, bot, e, pool, translation).await;
+            _ = log_error(chat, bot, e, storage, translation).await;
         }
     }
     Ok(())
 }
 
+//in group chats the member is known, administrators are looked up
+//only for the commands that need it
+async fn chat_user(
+    command: &Command,
+    chat: &Chat,
+    sender: Option<&User>,
+    anonymous_admin: bool,
+    bot: &Bot,
+) -> Result<process::ChatUser> {
+    let chat_id = chat.id.to_string();
+    let sender = match sender {
+        Some(sender) if !chat.is_private() => sender,
+        _ => return Ok(process::ChatUser::chat(chat_id)),
+    };
+    let is_admin = anonymous_admin
+        || (command.is_group_setting()
+            && bot.get_chat_member(chat.id, sender.id).await?.is_privileged());
+    Ok(process::ChatUser {
+        chat_id,
+        member: Some(process::Member {
+            user_id: sender.id.0 as i64,
+            name: sender.full_name(),
+            is_admin,
+        }),
+    })
+}
+
+//returns the language code, if the text is a command like /en or /en@bot_name
+pub(crate) fn language_command(
+    text: &str,
+    bot_name: &str,
+    translation: &translations::Translation,
+) -> Option<String> {
+    let command = text.trim().strip_prefix('/')?;
+    let code = match command.split_once('@') {
+        Some((code, name)) if name.eq_ignore_ascii_case(bot_name) => code,
+        Some(_) => return None,
+        None => command,
+    };
+    if translation.has_language(code) {
+        Some(code.to_string())
+    } else {
+        None
+    }
+}
+
+//the error is logged by its kind, and the user is told about it, if the chat is still there
 async fn log_error(
     chat: Chat,
     bot: Bot,
-    e: Error,
-    pool: &PgPool,
+    e: anyhow::Error,
+    storage: &dyn Storage,
     translation: &translations::Translation,
 ) -> Result<()> {
-    let err_str = format!("Error: {e:?}");
-    tracing::info!(err_str);
-    send_error_message("unknown_error", chat, bot, pool, translation).await?;
+    let error = Error::from(e);
+    error.log(&format!("Error in chat {}", chat.id));
+    if let Some(message_key) = error.message_key() {
+        send_error_message(message_key, chat, bot, storage, translation).await?;
+    }
     Ok(())
 }
 
 async fn send_error_message(message_key: &str, chat: Chat, bot: Bot, 
-    pool: &PgPool, translation: &translations::Translation) -> Result<()> {
-    let subscriber_id = database_interactions::get_subscriber_id(chat.id.to_string(), pool).await?;
+    storage: &dyn Storage, translation: &translations::Translation) -> Result<()> {
+    let subscriber_id = storage.get_subscriber_id(chat.id.to_string()).await?;
     let language = if let Some(subscriber_id) = subscriber_id {
-        database_interactions::get_language(subscriber_id, pool).await?
+        storage.get_language(subscriber_id).await?
     } else {
-        process::Language::En
+        translation.default_language().clone()
     };
     _ = bot
         .send_message(
             chat.id,
-            translation.get(message_key, language)?,
+            translation.get(message_key, &language)?,
         )
+        .parse_mode(ParseMode::Html)
         .await;
     Ok(())
 }
 
+//message_id is the message the action is the reply to, if there is one,
+//all texts are html, card texts are photo captions when they fit, otherwise they follow the photos
 pub async fn process_action(
     action: process::Action,
-    chat: Chat,
-    message_id: MessageId,
+    chat_id: ChatId,
+    message_id: Option<MessageId>,
     bot: Bot,
     q: Option<CallbackQuery>,
-    pool: &PgPool,
+    storage: &dyn Storage,
+    config: &Config,
 ) -> Result<()> {
+    //a card photo with the keyboard stays, only its keyboard is removed
+    let replied_to_photo = q
+        .as_ref()
+        .and_then(|q| q.message.as_ref())
+        .is_some_and(|message| message.photo().is_some());
     if let Some(q) = q {
         bot.answer_callback_query(q.id).await?;
     }
-    if action.delete_previous_message {
-        //if deletion failed, then whatever, it doesn't work after 48 hours
-        let _ = bot.delete_message(chat.id, message_id).await;
+    if replied_to_photo && (action.delete_previous_message || action.replacement_text.is_some()) {
+        if let Some(message_id) = message_id {
+            let _ = bot.edit_message_reply_markup(chat_id, message_id).await;
+        }
+        if let Some(replacement_text) = action.replacement_text {
+            bot.send_message(chat_id, replacement_text).parse_mode(ParseMode::Html).await?;
+        }
+    } else if action.delete_previous_message {
+        if let Some(message_id) = message_id {
+            //if deletion failed, then whatever, it doesn't work after 48 hours
+            let _ = bot.delete_message(chat_id, message_id).await;
+        }
     } else if let Some(replacement_text) = action.replacement_text {
-        let edit_result = bot
-            .edit_message_text(chat.id, message_id, replacement_text.clone())
-            .await;
-        if edit_result.is_err() {
+        let edited = match message_id {
+            Some(message_id) => bot
+                .edit_message_text(chat_id, message_id, replacement_text.clone())
+                .parse_mode(ParseMode::Html)
+                .await
+                .is_ok(),
+            None => false,
+        };
+        if !edited {
             //if editing message doesn't work (e.g., after 48 hours), 
             //then just send the text separately
-            bot.send_message(chat.id, replacement_text).await?;
+            bot.send_message(chat_id, replacement_text).parse_mode(ParseMode::Html).await?;
         }
     }
-    if let Some((input_file, image_description)) = action.image_data {
-        let result = bot.send_photo(chat.id, input_file).await?;
-        if let Some(filename) = action.filename {
-            let file_id = match result.kind {
-                MessageKind::Common(MessageCommon{media_kind: MediaKind::Photo(p), ..}) => {
-                     Some(p.photo.first().unwrap().file.id.clone())
-                },
-                _ => None,
-            };
-            if let Some(id) = file_id {
-                database_interactions::set_file_id(filename, id, pool).await?;
+    if action.images.len() == 1 {
+        let image = &action.images[0];
+        let with_keyboard = config.keyboard_on_photo;
+        let mut request = bot.send_photo(chat_id, image.input_file.clone());
+        let caption = markup::fits_caption(&image.text) && !image.text.is_empty();
+        if caption {
+            request = request.caption(image.text.clone()).parse_mode(ParseMode::Html);
+        }
+        //the keyboard goes to the last message of the card
+        if with_keyboard && (caption || image.text.is_empty()) {
+            request = request.reply_markup(action.keyboard.clone());
+        }
+        let result = request.await?;
+        save_file_id(result, image, storage).await?;
+        if !caption && !image.text.is_empty() {
+            let mut request = bot.send_message(chat_id, image.text.clone()).parse_mode(ParseMode::Html);
+            if with_keyboard {
+                request = request.reply_markup(action.keyboard.clone());
             }
+            request.await?;
         }
-        if !image_description.is_empty() {
-            bot.send_message(chat.id, image_description).await?;
+        if with_keyboard {
+            return Ok(());
+        }
+    } else if !action.images.is_empty() {
+        let media: Vec<InputMedia> = action
+            .images
+            .iter()
+            .map(|image| {
+                let mut photo = InputMediaPhoto::new(image.input_file.clone());
+                if markup::fits_caption(&image.text) {
+                    photo = photo.caption(image.text.clone()).parse_mode(ParseMode::Html);
+                }
+                InputMedia::Photo(photo)
+            })
+            .collect();
+        let results = bot.send_media_group(chat_id, media).await?;
+        for (result, image) in results.into_iter().zip(&action.images) {
+            save_file_id(result, image, storage).await?;
+        }
+        for image in action.images.iter().filter(|image| !markup::fits_caption(&image.text)) {
+            bot.send_message(chat_id, image.text.clone()).parse_mode(ParseMode::Html).await?;
         }
     }
-    bot.send_message(chat.id, action.new_message_text)
+    bot.send_message(chat_id, action.new_message_text)
+        .parse_mode(ParseMode::Html)
         .reply_markup(action.keyboard)
         .await?;
 
     Ok(())
 }
+
+//the largest size of the photo is saved, it is the uploaded image itself
+pub async fn save_file_id(message: Message, image: &process::Image, storage: &dyn Storage) -> Result<()> {
+    let file_id = match message.kind {
+        MessageKind::Common(MessageCommon{media_kind: MediaKind::Photo(p), ..}) => {
+             p.photo
+                .iter()
+                .max_by_key(|photo| photo.width * photo.height)
+                .map(|photo| photo.file.id.clone())
+        },
+        _ => None,
+    };
+    if let (Some(id), Some(content_hash)) = (file_id, &image.content_hash) {
+        storage.set_file_id(
+            image.card_id,
+            &image.language,
+            image.reversed,
+            id,
+            content_hash.clone(),
+        ).await?;
+    }
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,1136 @@
+use std::sync::Arc;
+
+use teloxide::types::InlineKeyboardButtonKind;
+use teloxide::{ApiError, RequestError};
+use time::Time;
+
+use crate::config::{self, Config, StartupCheck, WebhookConfig};
+use crate::deck_files;
+use crate::deck_import;
+use crate::markup;
+use crate::memory_storage::MemoryStorage;
+use crate::process::{self, Action, Language};
+use crate::sqlite_storage::SqliteStorage;
+use crate::storage::Storage;
+use crate::telegram_interactions::{self, Command};
+use crate::error::Error;
+use crate::{check, file_ids, spreads, translations, warmup};
+
+const PICTURES_PATH: &str = "pictures";
+const CHAT_ID: &str = "100";
+const OTHER_CHAT_ID: &str = "200";
+//the main deck in pictures has this many cards
+const CARDS_COUNT: usize = 2;
+
+struct TestBot {
+    storage: Arc<dyn Storage>,
+    translation: translations::Translation,
+    spreads: spreads::Spreads,
+    config: Config,
+}
+
+impl TestBot {
+    async fn new() -> TestBot {
+        TestBot::with_storage(Box::new(MemoryStorage::new())).await
+    }
+
+    async fn with_storage(storage: Box<dyn Storage>) -> TestBot {
+        storage.check_cards_table(PICTURES_PATH).await.unwrap();
+        let storage = storage.with_bot(storage.get_active_bots().await.unwrap()[0].id);
+        TestBot {
+            storage,
+            translation: translations::translation(PICTURES_PATH, "en").unwrap(),
+            spreads: spreads::spreads(PICTURES_PATH).unwrap(),
+            config: Config {
+                database_path: "memory:".to_string(),
+                bot_token: "token".to_string(),
+                database_max_connections: 1,
+                pictures_path: PICTURES_PATH.to_string(),
+                default_language: "en".to_string(),
+                log_level: "info".to_string(),
+                admin_chat_ids: vec![],
+                keyboard_on_photo: false,
+                startup_check: StartupCheck::Warn,
+                storage_chat_id: None,
+                webhook: None,
+            },
+        }
+    }
+
+    async fn send(&self, command: Command) -> Action {
+        self.send_from(CHAT_ID, command).await
+    }
+
+    async fn send_from(&self, chat_id: &str, command: Command) -> Action {
+        process::process_message(
+            command,
+            &process::ChatUser::chat(chat_id.to_string()),
+            &*self.storage,
+            &self.translation,
+            &self.spreads,
+            &self.config,
+        )
+        .await
+        .unwrap()
+    }
+
+    //a member of the group chat CHAT_ID
+    async fn send_as(&self, user_id: i64, is_admin: bool, command: Command) -> Action {
+        let user = process::ChatUser {
+            chat_id: CHAT_ID.to_string(),
+            member: Some(process::Member {
+                user_id,
+                name: format!("Member {}", user_id),
+                is_admin,
+            }),
+        };
+        process::process_message(
+            command,
+            &user,
+            &*self.storage,
+            &self.translation,
+            &self.spreads,
+            &self.config,
+        )
+        .await
+        .unwrap()
+    }
+
+    fn text(&self, key: &str, language: &str) -> String {
+        self.translation.get(key, &Language::new(language)).unwrap()
+    }
+
+    fn text_with(&self, key: &str, language: &str, args: &[(&str, String)]) -> String {
+        self.translation.get_with(key, &Language::new(language), args).unwrap()
+    }
+
+    async fn subscriber_id(&self) -> i32 {
+        self.storage
+            .get_subscriber_id(CHAT_ID.to_string())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+}
+
+fn callbacks(action: &Action) -> Vec<String> {
+    action
+        .keyboard
+        .inline_keyboard
+        .iter()
+        .flatten()
+        .filter_map(|button| match &button.kind {
+            InlineKeyboardButtonKind::CallbackData(data) => Some(data.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn start_creates_subscriber_and_shows_main_menu() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::Start).await;
+    assert_eq!(action.new_message_text, bot.text("start", "en"));
+    assert_eq!(callbacks(&action), vec!["cd", "sp", "sh", "hs:0", "st", "ab"]);
+    assert!(action.images.is_empty());
+    let subscriber_id = bot.subscriber_id().await;
+    assert_eq!(bot.storage.get_language(subscriber_id).await.unwrap(), Language::new("en"));
+}
+
+#[tokio::test]
+async fn help_replaces_previous_message() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::Help).await;
+    assert_eq!(action.new_message_text, bot.text("help", "en"));
+    assert!(action.delete_previous_message);
+}
+
+#[tokio::test]
+async fn about_shows_description() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::About).await;
+    assert_eq!(action.replacement_text, Some(bot.text("description", "en")));
+    assert_eq!(action.new_message_text, bot.text("choose_your_action", "en"));
+}
+
+#[tokio::test]
+async fn main_menu_shows_main_keyboard() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::MainMenu).await;
+    assert!(action.delete_previous_message);
+    assert_eq!(callbacks(&action)[0], "cd");
+}
+
+#[tokio::test]
+async fn card_draws_every_card_once() {
+    let bot = TestBot::new().await;
+    let mut drawn = vec![];
+    for _ in 0..CARDS_COUNT {
+        let action = bot.send(Command::Card).await;
+        assert_eq!(action.images.len(), 1);
+        let image = &action.images[0];
+        assert!(!image.reversed);
+        assert!(!drawn.contains(&image.card_id));
+        drawn.push(image.card_id);
+    }
+    let action = bot.send(Command::Card).await;
+    assert!(action.images.is_empty());
+    assert_eq!(action.new_message_text, bot.text_with("no_cards_left", "en", &[("deck", "Main".to_string())]));
+    let subscriber_id = bot.subscriber_id().await;
+    assert_eq!(bot.storage.count_draws(subscriber_id).await.unwrap(), CARDS_COUNT as i64);
+}
+
+#[tokio::test]
+async fn spread_without_id_lists_spreads() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::Spread(String::new())).await;
+    assert_eq!(action.new_message_text, bot.text("choose_spread", "en"));
+    let callbacks = callbacks(&action);
+    assert!(callbacks.contains(&"sp:advice".to_string()));
+    assert!(callbacks.contains(&"sp:cross".to_string()));
+}
+
+#[tokio::test]
+async fn spread_draws_a_card_for_every_position() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::Spread("advice".to_string())).await;
+    assert_eq!(action.images.len(), 2);
+    assert!(action.images[0].text.starts_with("Situation\n"));
+    assert!(action.images[1].text.starts_with("Advice\n"));
+    assert_ne!(action.images[0].card_id, action.images[1].card_id);
+}
+
+#[tokio::test]
+async fn spread_needs_enough_cards() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::Spread("three".to_string())).await;
+    assert!(action.images.is_empty());
+    assert_eq!(action.new_message_text, bot.text("not_enough_cards_for_spread", "en"));
+    //nothing is drawn, so a single card is still there
+    assert_eq!(bot.send(Command::Card).await.images.len(), 1);
+}
+
+#[tokio::test]
+async fn unknown_spread_is_reported() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::Spread("unknown".to_string())).await;
+    assert_eq!(action.new_message_text, bot.text("spread_not_found", "en"));
+}
+
+#[tokio::test]
+async fn shuffle_returns_drawn_cards() {
+    let bot = TestBot::new().await;
+    for _ in 0..CARDS_COUNT {
+        bot.send(Command::Card).await;
+    }
+    let action = bot.send(Command::Shuffle).await;
+    let args = [("remaining", CARDS_COUNT.to_string()), ("deck", "Main".to_string())];
+    assert_eq!(action.replacement_text, Some(bot.text_with("cards_shuffled_back", "en", &args)));
+    assert_eq!(action.replacement_text.unwrap(), "Cards shuffled back, 2 cards in the deck Main");
+    assert_eq!(bot.send(Command::Card).await.images.len(), 1);
+}
+
+#[tokio::test]
+async fn history_is_empty_before_draws() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::History(String::new())).await;
+    assert_eq!(action.new_message_text, bot.text("history_empty", "en"));
+    assert_eq!(callbacks(&action), vec!["mm"]);
+}
+
+#[tokio::test]
+async fn history_lists_draws() {
+    let bot = TestBot::new().await;
+    bot.send(Command::Card).await;
+    let action = bot.send(Command::History("0".to_string())).await;
+    assert_eq!(action.new_message_text, format!("{} (1/1)", bot.text("history", "en")));
+    assert_eq!(callbacks(&action), vec!["he:1", "mm"]);
+}
+
+async fn check_untranslated_draws(bot: TestBot) {
+    bot.send(Command::Card).await;
+    let subscriber_id = bot.subscriber_id().await;
+    let untranslated = Language::new("xx");
+    assert_eq!(bot.storage.count_draws(subscriber_id).await.unwrap(), 1);
+    let draws = bot.storage.get_draws(&untranslated, 0, 10, subscriber_id).await.unwrap();
+    assert_eq!(draws.len(), 1);
+    assert!(!draws[0].name.is_empty());
+}
+
+#[tokio::test]
+async fn draws_without_translations_are_listed() {
+    check_untranslated_draws(TestBot::new().await).await;
+    let storage = SqliteStorage::new("sqlite::memory:", 1).await.unwrap();
+    check_untranslated_draws(TestBot::with_storage(Box::new(storage)).await).await;
+}
+
+#[tokio::test]
+async fn history_entry_shows_drawn_card_again() {
+    let bot = TestBot::new().await;
+    let card_id = bot.send(Command::Card).await.images[0].card_id;
+    let action = bot.send(Command::HistoryEntry("1".to_string())).await;
+    assert_eq!(action.images.len(), 1);
+    assert_eq!(action.images[0].card_id, card_id);
+    //the card isn't drawn again
+    let subscriber_id = bot.subscriber_id().await;
+    assert_eq!(bot.storage.count_draws(subscriber_id).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn history_entry_of_another_chat_is_not_found() {
+    let bot = TestBot::new().await;
+    bot.send_from(OTHER_CHAT_ID, Command::Card).await;
+    let action = bot.send(Command::HistoryEntry("1".to_string())).await;
+    assert!(action.images.is_empty());
+    assert_eq!(action.new_message_text, bot.text("draw_not_found", "en"));
+}
+
+#[tokio::test]
+async fn language_without_code_lists_languages() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::Language(String::new())).await;
+    assert_eq!(action.new_message_text, bot.text("language_settings", "en"));
+    assert_eq!(callbacks(&action), vec!["lg:en", "lg:ru", "st"]);
+}
+
+#[tokio::test]
+async fn language_is_set() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::Language("ru".to_string())).await;
+    assert_eq!(action.replacement_text, Some(bot.text("language_set", "ru")));
+    let subscriber_id = bot.subscriber_id().await;
+    assert_eq!(bot.storage.get_language(subscriber_id).await.unwrap(), Language::new("ru"));
+    let action = bot.send(Command::Card).await;
+    assert_eq!(action.images[0].language, Language::new("ru"));
+}
+
+#[tokio::test]
+async fn unknown_language_is_not_set() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::Language("xx".to_string())).await;
+    assert_eq!(action.replacement_text, None);
+    let subscriber_id = bot.subscriber_id().await;
+    assert_eq!(bot.storage.get_language(subscriber_id).await.unwrap(), Language::new("en"));
+}
+
+#[tokio::test]
+async fn decks_shows_current_deck() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::Decks(String::new())).await;
+    assert_eq!(
+        action.new_message_text,
+        format!(
+            "{}\n{}",
+            bot.text("deck_settings", "en"),
+            bot.text_with("current_deck", "en", &[("deck", "Main".to_string())])
+        )
+    );
+    assert_eq!(callbacks(&action), vec!["dk:1", "st"]);
+}
+
+#[tokio::test]
+async fn deck_is_selected() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::Decks("1".to_string())).await;
+    assert_eq!(action.replacement_text, Some(bot.text_with("deck_selected", "en", &[("deck", "Main".to_string())])));
+    let action = bot.send(Command::Decks("99".to_string())).await;
+    assert_eq!(action.replacement_text, None);
+    let subscriber_id = bot.subscriber_id().await;
+    assert_eq!(bot.storage.get_deck(subscriber_id).await.unwrap().id, 1);
+}
+
+#[tokio::test]
+async fn settings_shows_settings_keyboard() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::Settings).await;
+    assert_eq!(action.new_message_text, bot.text("settings", "en"));
+    assert_eq!(callbacks(&action), vec!["lg", "ds", "dk", "rv", "dc", "mm"]);
+}
+
+#[tokio::test]
+async fn description_shows_descriptions_keyboard() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::Description).await;
+    assert_eq!(action.new_message_text, bot.text("descriptions_settings", "en"));
+    assert_eq!(callbacks(&action), vec!["fd", "no", "nd", "st"]);
+}
+
+#[tokio::test]
+async fn full_description_shows_name_and_description() {
+    let bot = TestBot::new().await;
+    bot.send(Command::NoDescription).await;
+    let action = bot.send(Command::FullDescription).await;
+    assert_eq!(action.replacement_text, Some(bot.text("full_descriptions_will_be_shown", "en")));
+    let subscriber_id = bot.subscriber_id().await;
+    assert_eq!(
+        bot.storage.get_descriptions_format(subscriber_id).await.unwrap(),
+        process::FULL_DESCRIPTIONS
+    );
+    let text = &bot.send(Command::Card).await.images[0].text;
+    assert!(text.ends_with(" card description"));
+}
+
+#[tokio::test]
+async fn names_only_shows_name() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::NamesOnly).await;
+    assert_eq!(action.replacement_text, Some(bot.text("names_only_will_be_shown", "en")));
+    let text = &bot.send(Command::Card).await.images[0].text;
+    assert!(text == "<b>Gefest</b>" || text == "<b>Shiva</b>");
+}
+
+#[tokio::test]
+async fn no_description_shows_no_text() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::NoDescription).await;
+    assert_eq!(action.replacement_text, Some(bot.text("no_descriptions", "en")));
+    assert!(bot.send(Command::Card).await.images[0].text.is_empty());
+}
+
+#[tokio::test]
+async fn daily_card_without_argument_shows_settings() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::DailyCard(String::new())).await;
+    assert_eq!(
+        action.new_message_text,
+        format!("{}\n{}", bot.text("daily_card_settings", "en"), bot.text("daily_card_off", "en"))
+    );
+    assert!(callbacks(&action).contains(&"dc:off".to_string()));
+}
+
+#[tokio::test]
+async fn daily_card_is_set_and_turned_off() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::DailyCard("09:30 +03:00".to_string())).await;
+    assert_eq!(
+        action.replacement_text,
+        Some(format!("{} 09:30 UTC+03:00", bot.text("daily_card_on", "en")))
+    );
+    let subscriber_id = bot.subscriber_id().await;
+    assert_eq!(
+        bot.storage.get_daily_card(subscriber_id).await.unwrap(),
+        (Some(Time::from_hms(9, 30, 0).unwrap()), 180)
+    );
+    assert_eq!(bot.storage.get_daily_card_subscribers().await.unwrap().len(), 1);
+
+    let action = bot.send(Command::DailyCard("off".to_string())).await;
+    assert_eq!(action.replacement_text, Some(bot.text("daily_card_off", "en")));
+    assert_eq!(bot.storage.get_daily_card(subscriber_id).await.unwrap(), (None, 180));
+}
+
+#[tokio::test]
+async fn daily_card_wrong_format_is_reported() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::DailyCard("25:00".to_string())).await;
+    assert_eq!(action.replacement_text, Some(bot.text("daily_card_wrong_format", "en")));
+    let subscriber_id = bot.subscriber_id().await;
+    assert_eq!(bot.storage.get_daily_card(subscriber_id).await.unwrap(), (None, 0));
+}
+
+#[tokio::test]
+async fn reversed_shows_reversed_keyboard() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::Reversed).await;
+    assert_eq!(action.new_message_text, bot.text("reversed_settings", "en"));
+    assert_eq!(callbacks(&action), vec!["rv:1", "rv:0", "st"]);
+}
+
+#[tokio::test]
+async fn reversed_on_allows_reversed_cards() {
+    let bot = TestBot::new().await;
+    let action = bot.send(Command::ReversedOn).await;
+    assert_eq!(action.replacement_text, Some(bot.text("reversed_cards_enabled", "en")));
+    //with two cards and a fair coin, some card is drawn reversed sooner or later
+    let mut reversed = false;
+    for _ in 0..50 {
+        let action = bot.send(Command::Spread("advice".to_string())).await;
+        if action.images.iter().any(|image| image.reversed) {
+            reversed = true;
+            break;
+        }
+        bot.send(Command::Shuffle).await;
+    }
+    assert!(reversed);
+}
+
+#[tokio::test]
+async fn reversed_off_draws_upright_cards() {
+    let bot = TestBot::new().await;
+    bot.send(Command::ReversedOn).await;
+    let action = bot.send(Command::ReversedOff).await;
+    assert_eq!(action.replacement_text, Some(bot.text("reversed_cards_disabled", "en")));
+    for _ in 0..10 {
+        let action = bot.send(Command::Spread("advice".to_string())).await;
+        assert!(action.images.iter().all(|image| !image.reversed));
+        bot.send(Command::Shuffle).await;
+    }
+}
+
+#[tokio::test]
+async fn unknown_environment_variables_are_ignored() {
+    let vars = [
+        ("DECKBOT_BOT_TOKEN", "env_token"),
+        ("DECKBOT_WEBHOOK_URL", "https://example.com/bot"),
+        ("DECKBOT_VERSION", "1.2"),
+        ("DECKBOT_WEBHOOK_PORT", "8443"),
+        ("HOME", "/root"),
+    ];
+    let mut object = serde_json::Map::new();
+    let ignored = config::apply_env_overrides(
+        &mut object,
+        vars.iter().map(|(name, value)| (name.to_string(), value.to_string())),
+    )
+    .unwrap();
+    assert_eq!(ignored, vec!["DECKBOT_VERSION", "DECKBOT_WEBHOOK_PORT"]);
+    assert_eq!(object["bot_token"], "env_token");
+    assert_eq!(object["webhook"], serde_json::json!({"url": "https://example.com/bot"}));
+
+    //every field but the webhook section can be set
+    let mut config = TestBot::new().await.config;
+    config.webhook = Some(WebhookConfig {
+        address: "127.0.0.1:8443".parse().unwrap(),
+        url: "https://example.com/bot".to_string(),
+        secret_token: None,
+        certificate_path: None,
+    });
+    let value = serde_json::to_value(&config).unwrap();
+    let fields: Vec<&str> = value.as_object().unwrap().keys().map(|key| key.as_str()).collect();
+    let webhook_fields: Vec<&str> = value["webhook"].as_object().unwrap().keys().map(|key| key.as_str()).collect();
+    assert_eq!(fields.len(), config::ENV_FIELDS.len() + 1);
+    assert!(config::ENV_FIELDS.iter().all(|field| fields.contains(field)));
+    assert!(config::ENV_WEBHOOK_FIELDS.iter().all(|field| webhook_fields.contains(field)));
+    assert_eq!(webhook_fields.len(), config::ENV_WEBHOOK_FIELDS.len());
+}
+
+#[test]
+fn webhook_url_must_be_http() {
+    let path = std::env::temp_dir().join(format!("deckbot_config_{}.json", std::process::id()));
+    let config = |url: &str| {
+        format!(
+            r#"{{"database_path": "memory:", "bot_token": "token", "pictures_path": "{}",
+            "webhook": {{"address": "127.0.0.1:8443", "url": "{}"}}}}"#,
+            PICTURES_PATH, url
+        )
+    };
+    std::fs::write(&path, config("https://example.com/bot")).unwrap();
+    assert!(Config::load(&path.to_string_lossy()).is_ok());
+    std::fs::write(&path, config("mailto:bot@example.com")).unwrap();
+    let error = Config::load(&path.to_string_lossy()).err().unwrap().to_string();
+    assert!(error.contains("webhook.url: unsupported scheme mailto"), "{}", error);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn sqlite_storage_keeps_chat_state() {
+    //every connection to an in-memory database gets its own database
+    let storage = SqliteStorage::new("sqlite::memory:", 1).await.unwrap();
+    let bot = TestBot::with_storage(Box::new(storage)).await;
+    bot.send(Command::Start).await;
+    let subscriber_id = bot.subscriber_id().await;
+
+    let card_id = bot.send(Command::Card).await.images[0].card_id;
+    assert_eq!(bot.send(Command::Card).await.images.len(), 1);
+    assert!(bot.send(Command::Card).await.images.is_empty());
+    let action = bot.send(Command::History(String::new())).await;
+    assert_eq!(callbacks(&action), vec!["he:2", "he:1", "mm"]);
+    assert_eq!(bot.send(Command::HistoryEntry("1".to_string())).await.images[0].card_id, card_id);
+    bot.send(Command::Shuffle).await;
+    assert_eq!(bot.send(Command::Spread("advice".to_string())).await.images.len(), 2);
+
+    bot.send(Command::Language("ru".to_string())).await;
+    assert_eq!(bot.storage.get_language(subscriber_id).await.unwrap(), Language::new("ru"));
+    bot.send(Command::Decks("1".to_string())).await;
+    assert_eq!(bot.storage.get_deck(subscriber_id).await.unwrap().name, "Main");
+    bot.send(Command::NamesOnly).await;
+    assert_eq!(bot.storage.get_descriptions_format(subscriber_id).await.unwrap(), process::NAMES_ONLY);
+    bot.send(Command::ReversedOn).await;
+    bot.send(Command::DailyCard("21:00 -05:00".to_string())).await;
+    assert_eq!(
+        bot.storage.get_daily_card(subscriber_id).await.unwrap(),
+        (Some(Time::from_hms(21, 0, 0).unwrap()), -300)
+    );
+    let date = time::OffsetDateTime::now_utc().date();
+    assert!(bot.storage.mark_daily_card_sent(subscriber_id, date).await.unwrap());
+    assert!(!bot.storage.mark_daily_card_sent(subscriber_id, date).await.unwrap());
+
+    let stats = bot.storage.get_stats().await.unwrap();
+    assert_eq!(stats.subscribers, 1);
+    assert_eq!(stats.new_subscribers, 1);
+    assert_eq!(stats.daily_card_subscribers, 1);
+    assert_eq!(stats.draws_today, 4);
+    assert_eq!(stats.languages, vec![("ru".to_string(), 1)]);
+    let chat_state = bot.storage.get_chat_state(CHAT_ID.to_string()).await.unwrap().unwrap();
+    assert_eq!(chat_state.drawn_cards, 2);
+    assert_eq!(chat_state.draws, 4);
+    assert!(chat_state.reversed_cards);
+    assert_eq!(bot.storage.get_subscribers_chat_ids().await.unwrap(), vec![CHAT_ID.to_string()]);
+}
+
+#[tokio::test]
+async fn groups_get_answers_only_to_addressed_commands() {
+    let bot = TestBot::new().await;
+    assert!(telegram_interactions::is_addressed("/card@DeckBot", "deckbot"));
+    assert!(telegram_interactions::is_addressed("/spread@deckbot 3", "deckbot"));
+    assert!(!telegram_interactions::is_addressed("/card", "deckbot"));
+    assert!(!telegram_interactions::is_addressed("/card@otherbot", "deckbot"));
+    let language = |text| telegram_interactions::language_command(text, "mybot", &bot.translation);
+    assert_eq!(language("/en@MyBot"), Some("en".to_string()));
+    assert_eq!(language("/ru"), Some("ru".to_string()));
+    assert_eq!(language("/en@otherbot"), None);
+}
+
+#[tokio::test]
+async fn group_settings_are_for_admins_only() {
+    let bot = TestBot::new().await;
+    let action = bot.send_as(1, false, Command::ReversedOn).await;
+    assert_eq!(action.new_message_text, bot.text("group_settings_admins_only", "en"));
+    let subscriber_id = bot.subscriber_id().await;
+    assert!(bot.storage.get_chat_state(CHAT_ID.to_string()).await.unwrap().is_some_and(|state| !state.reversed_cards));
+    let action = bot.send_as(2, true, Command::Settings).await;
+    assert!(callbacks(&action).contains(&"gd".to_string()));
+    assert!(!callbacks(&bot.send_from(OTHER_CHAT_ID, Command::Settings).await).contains(&"gd".to_string()));
+    bot.send_as(2, true, Command::MemberDecks).await;
+    assert!(bot.storage.get_per_member_decks(subscriber_id).await.unwrap());
+}
+
+#[tokio::test]
+async fn group_members_share_the_deck_by_default() {
+    let bot = TestBot::new().await;
+    let action = bot.send_as(1, false, Command::Card).await;
+    let drawn_by = bot.text_with("drawn_by", "en", &[("user", "Member 1".to_string())]);
+    assert!(action.images[0].text.starts_with(&drawn_by));
+    assert_eq!(bot.send_as(2, false, Command::Card).await.images.len(), 1);
+    assert!(bot.send_as(1, false, Command::Card).await.images.is_empty());
+}
+
+async fn check_member_decks(bot: TestBot) {
+    bot.send_as(1, true, Command::MemberDecks).await;
+    for user_id in [1, 2] {
+        for _ in 0..CARDS_COUNT {
+            assert_eq!(bot.send_as(user_id, false, Command::Card).await.images.len(), 1);
+        }
+        assert!(bot.send_as(user_id, false, Command::Card).await.images.is_empty());
+    }
+    bot.send_as(1, false, Command::Shuffle).await;
+    assert_eq!(bot.send_as(1, false, Command::Card).await.images.len(), 1);
+    assert!(bot.send_as(2, false, Command::Card).await.images.is_empty());
+    let action = bot.send_as(2, false, Command::History(String::new())).await;
+    assert_eq!(callbacks(&action).len(), CARDS_COUNT + 1);
+
+    //settings of the chat reach the members who already have their decks,
+    //cards are drawn from storage to skip rotating the images
+    bot.send_as(1, true, Command::ReversedOn).await;
+    let member_id = bot.storage.get_member_id(CHAT_ID.to_string(), 2).await.unwrap().unwrap();
+    let language = Language::new("en");
+    let mut reversed = false;
+    for _ in 0..50 {
+        bot.storage.shuffle_cards_back(member_id).await.unwrap();
+        let (_, cards) = bot.storage.draw_cards(1, None, &language, member_id).await.unwrap().unwrap();
+        if cards[0].1 {
+            reversed = true;
+            break;
+        }
+    }
+    assert!(reversed);
+
+    let stats = bot.storage.get_stats().await.unwrap();
+    assert_eq!(stats.subscribers, 1);
+    assert_eq!(bot.storage.get_subscribers_chat_ids().await.unwrap(), vec![CHAT_ID.to_string()]);
+    assert_eq!(bot.storage.get_chat_state(CHAT_ID.to_string()).await.unwrap().unwrap().drawn_cards, 0);
+}
+
+#[tokio::test]
+async fn group_members_draw_from_their_own_decks() {
+    check_member_decks(TestBot::new().await).await;
+}
+
+#[tokio::test]
+async fn sqlite_storage_keeps_member_decks() {
+    let storage = SqliteStorage::new("sqlite::memory:", 1).await.unwrap();
+    check_member_decks(TestBot::with_storage(Box::new(storage)).await).await;
+}
+
+#[tokio::test]
+async fn inline_cards_are_the_uploaded_ones() {
+    let bot = TestBot::new().await;
+    let inline_cards = |query: &'static str| {
+        process::inline_cards(query, CHAT_ID.to_string(), Some("en"), &*bot.storage, &bot.translation)
+    };
+    assert!(inline_cards("").await.unwrap().is_empty());
+    let language = Language::new("en");
+    let card_ids: Vec<i32> = bot.storage.search_cards("", &language, 1).await.unwrap().iter().map(|card| card.id).collect();
+    assert_eq!(card_ids.len(), CARDS_COUNT);
+    bot.storage.set_file_id(card_ids[0], &language, false, "file".to_string(), "hash".to_string()).await.unwrap();
+
+    let cards = inline_cards("").await.unwrap();
+    assert_eq!(cards.len(), 1);
+    assert_eq!(cards[0].file_id.as_deref(), Some("file"));
+    let name = bot.storage.search_cards("", &language, 1).await.unwrap()[0].name.clone();
+    let found = bot.storage.search_cards(&name[1..].to_uppercase(), &language, 1).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, card_ids[0]);
+    assert!(inline_cards("no such card").await.unwrap().is_empty());
+    //inline results are not drawn
+    assert_eq!(bot.storage.count_draws(bot.subscriber_id().await).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn find_shows_matching_cards() {
+    let bot = TestBot::new().await;
+    assert_eq!(bot.send(Command::Find(String::new())).await.new_message_text, bot.text("find_usage", "en"));
+    let action = bot.send(Command::Find(" SHIV ".to_string())).await;
+    assert_eq!(action.images.len(), 1);
+    assert!(action.images[0].text.starts_with("<b>Shiva</b>\n"));
+    let action = bot.send(Command::Find("s".to_string())).await;
+    assert_eq!(action.new_message_text, bot.text("found_cards", "en"));
+    assert_eq!(callbacks(&action).len(), CARDS_COUNT + 1);
+    let action = bot.send(Command::Find("zeus".to_string())).await;
+    assert_eq!(action.new_message_text, bot.text("card_not_found", "en"));
+}
+
+#[tokio::test]
+async fn deck_browser_does_not_draw_cards() {
+    let bot = TestBot::new().await;
+    bot.send(Command::NoDescription).await;
+    let action = bot.send(Command::Deck(String::new())).await;
+    assert_eq!(action.new_message_text, "Main (1/1)");
+    let card_callbacks: Vec<String> = callbacks(&action)
+        .into_iter()
+        .filter(|data| data.starts_with("bc:"))
+        .collect();
+    assert_eq!(card_callbacks.len(), CARDS_COUNT);
+    for data in card_callbacks {
+        let card_id = data.trim_start_matches("bc:").to_string();
+        let action = bot.send(Command::DeckCard(card_id)).await;
+        //descriptions are shown, whatever the settings
+        assert!(action.images[0].text.contains("description"));
+    }
+    assert_eq!(bot.storage.count_draws(bot.subscriber_id().await).await.unwrap(), 0);
+    for _ in 0..CARDS_COUNT {
+        assert_eq!(bot.send(Command::Card).await.images.len(), 1);
+    }
+    let action = bot.send(Command::DeckCard("0".to_string())).await;
+    assert_eq!(action.new_message_text, bot.text("card_not_found", "en"));
+}
+
+//the main deck exported to a temp folder, with the cards changed by edit and deck.yml written,
+//to be imported to the empty pictures folder next to it, the folders are removed on drop
+struct ExportedDeck {
+    root: std::path::PathBuf,
+    dir: std::path::PathBuf,
+    pictures_path: String,
+    export: deck_import::ExportReport,
+    cards: Vec<deck_files::CardRecord>,
+}
+
+impl ExportedDeck {
+    async fn new(
+        bot: &TestBot,
+        name: &str,
+        deck_yml: &str,
+        edit: impl FnOnce(&mut [deck_files::CardRecord]),
+    ) -> ExportedDeck {
+        let root = std::env::temp_dir().join(format!("deckbot_{}_{}", name, std::process::id()));
+        let (dir, pictures_path) = (root.join(name), root.join("pictures"));
+        std::fs::create_dir_all(&pictures_path).unwrap();
+        let export = deck_import::export(&dir, None, PICTURES_PATH, &*bot.storage).await.unwrap();
+        let mut cards = deck_files::read_cards(&dir.to_string_lossy(), ".").unwrap();
+        edit(&mut cards);
+        deck_files::write_cards(&dir, &cards).unwrap();
+        std::fs::write(dir.join("deck.yml"), deck_yml).unwrap();
+        ExportedDeck {
+            root,
+            dir,
+            pictures_path: pictures_path.to_string_lossy().to_string(),
+            export,
+            cards,
+        }
+    }
+
+    async fn import(&self, bot: &TestBot, dry_run: bool) -> anyhow::Result<deck_import::ImportReport> {
+        deck_import::import(&self.dir, &self.pictures_path, &*bot.storage, dry_run).await
+    }
+}
+
+impl Drop for ExportedDeck {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+#[tokio::test]
+async fn deck_is_exported_and_imported_by_file_names() {
+    let bot = TestBot::new().await;
+    let mut exported = ExportedDeck::new(&bot, "tarot", "name: Tarot", |_| {}).await;
+    assert_eq!((exported.export.cards, exported.export.images), (CARDS_COUNT, 2 * CARDS_COUNT));
+
+    let report = exported.import(&bot, true).await.unwrap();
+    assert_eq!((report.deck_name.as_str(), report.deck_path.as_str()), ("Tarot", "decks/tarot"));
+    assert_eq!(report.added.len(), CARDS_COUNT);
+    assert_eq!(bot.storage.get_decks().await.unwrap().len(), 1);
+    exported.import(&bot, false).await.unwrap();
+    let deck = bot.storage.get_decks().await.unwrap().pop().unwrap();
+    assert_eq!(deck.name, "Tarot");
+    assert_eq!(bot.storage.get_deck_cards(deck.id).await.unwrap().len(), CARDS_COUNT);
+    assert!(exported.root.join("pictures/decks/tarot/en/shiva.png").is_file());
+
+    let cards = &mut exported.cards;
+    cards[0].translations[0].name = "Renamed".to_string();
+    deck_files::write_cards(&exported.dir, cards).unwrap();
+    std::fs::write(exported.dir.join("ru").join(&cards[1].filename), "new image").unwrap();
+    let report = exported.import(&bot, false).await.unwrap();
+    let cards = &exported.cards;
+    assert_eq!(report.changed, vec![cards[0].filename.clone()]);
+    assert_eq!(report.unchanged, 1);
+    assert_eq!(report.changed_images, vec![(cards[1].filename.clone(), "ru".to_string())]);
+    assert_eq!(bot.storage.get_deck_cards(deck.id).await.unwrap()[0].translations[0].name, "Renamed");
+
+    std::fs::remove_file(exported.dir.join("en").join(&cards[0].filename)).unwrap();
+    assert!(deck_import::check_deck_folder(&exported.dir).is_err());
+}
+
+async fn check_file_ids_follow_images(storage: Box<dyn Storage>) {
+    let bot = TestBot::with_storage(storage).await;
+    let language = Language::new("en");
+    let cards = bot.storage.search_cards("", &language, 1).await.unwrap();
+    let image = std::path::Path::new(PICTURES_PATH).join("en").join(&cards[0].filename);
+    let hash = file_ids::content_hash(&image).unwrap();
+    bot.storage.set_file_id(cards[0].id, &language, false, "old".to_string(), String::new()).await.unwrap();
+    bot.storage.set_file_id(cards[0].id, &language, true, "current".to_string(), hash.clone()).await.unwrap();
+    bot.storage.set_file_id(cards[1].id, &language, false, "changed".to_string(), "hash".to_string()).await.unwrap();
+
+    assert_eq!(file_ids::check_file_ids(PICTURES_PATH, &*bot.storage).await.unwrap(), 2);
+    let file_ids = bot.storage.get_file_ids().await.unwrap();
+    assert_eq!(file_ids.len(), 1);
+    assert!(file_ids.iter().all(|file_id| file_id.card_id == cards[0].id && file_id.content_hash == hash));
+    let card = &bot.storage.search_cards(&cards[0].name, &language, 1).await.unwrap()[0];
+    assert_eq!(card.telegram_file_id, None);
+    assert_eq!(card.reversed_telegram_file_id.as_deref(), Some("current"));
+}
+
+#[tokio::test]
+async fn file_ids_of_changed_images_are_forgotten() {
+    check_file_ids_follow_images(Box::new(MemoryStorage::new())).await;
+}
+
+#[tokio::test]
+async fn sqlite_storage_forgets_file_ids_of_changed_images() {
+    check_file_ids_follow_images(Box::new(SqliteStorage::new("sqlite::memory:", 1).await.unwrap())).await;
+}
+
+#[tokio::test]
+async fn warmup_skips_images_with_file_ids() {
+    let bot = TestBot::new().await;
+    let languages: Vec<Language> = bot.translation.languages().collect();
+    assert_eq!(languages.len(), 2);
+    let (images, report) = warmup::pending_images(&languages, PICTURES_PATH, &*bot.storage).await.unwrap();
+    assert_eq!(images.len(), 2 * CARDS_COUNT);
+    assert_eq!(report.cached, 0);
+    assert!(report.missing.is_empty() && report.invalid.is_empty());
+
+    let image = &images[0];
+    bot.storage
+        .set_file_id(image.card_id, &image.language, image.reversed, "file".to_string(), "hash".to_string())
+        .await
+        .unwrap();
+    let (images, report) = warmup::pending_images(&languages, PICTURES_PATH, &*bot.storage).await.unwrap();
+    assert_eq!(images.len(), 2 * CARDS_COUNT - 1);
+    assert_eq!(report.cached, 1);
+    let (images, report) = warmup::pending_images(&languages, "no_such_folder", &*bot.storage).await.unwrap();
+    assert!(images.is_empty());
+    assert_eq!(report.missing.len(), 2 * CARDS_COUNT - 1);
+}
+
+#[tokio::test]
+async fn warmup_uploads_reversed_images_only_when_chats_draw_them() {
+    let bot = TestBot::new().await;
+    let languages: Vec<Language> = bot.translation.languages().collect();
+    let (images, _) = warmup::pending_images(&languages, PICTURES_PATH, &*bot.storage).await.unwrap();
+    assert!(images.iter().all(|image| !image.reversed));
+
+    bot.send(Command::ReversedOn).await;
+    let (images, _) = warmup::pending_images(&languages, PICTURES_PATH, &*bot.storage).await.unwrap();
+    assert_eq!(images.len(), 2 * 2 * CARDS_COUNT);
+    assert_eq!(images.iter().filter(|image| image.reversed).count(), 2 * CARDS_COUNT);
+}
+
+#[tokio::test]
+async fn bots_have_their_own_chats_and_file_ids() {
+    let path = std::env::temp_dir().join(format!("deckbot_bots_{}.db", std::process::id()));
+    let database_path = format!("sqlite://{}", path.display());
+    let storage = SqliteStorage::new(&database_path, 1).await.unwrap();
+    let pool = sqlx::SqlitePool::connect(&database_path).await.unwrap();
+    sqlx::query("INSERT INTO bots (name, active, key) VALUES ('Staging', true, 'staging_token'), ('Old', false, 'old')")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let bots = storage.get_active_bots().await.unwrap();
+    assert_eq!(bots.len(), 2);
+    assert_eq!(bots[0].token(&TestBot::new().await.config), "token");
+    assert_eq!(bots[1].key, "staging_token");
+
+    check_bots_are_separated(TestBot::with_storage(Box::new(storage)).await, bots[1].id).await;
+    drop(pool);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn webhooks_of_bots_have_their_own_paths() {
+    let webhook = WebhookConfig {
+        address: "127.0.0.1:8443".parse().unwrap(),
+        url: "https://example.com/bot/".to_string(),
+        secret_token: None,
+        certificate_path: None,
+    };
+    assert_eq!(webhook.options(1).unwrap().url.as_str(), "https://example.com/bot/1");
+    assert_eq!(webhook.options(2).unwrap().url.path(), "/bot/2");
+    let webhook = WebhookConfig {
+        url: "mailto:bot@example.com".to_string(),
+        ..webhook
+    };
+    assert!(webhook.options(1).err().unwrap().to_string().contains("must be an http or https url"));
+}
+
+#[tokio::test]
+async fn memory_storage_keeps_bots_apart() {
+    check_bots_are_separated(TestBot::new().await, 2).await;
+}
+
+async fn check_bots_are_separated(main: TestBot, staging_id: i32) {
+    let staging = TestBot {
+        storage: main.storage.with_bot(staging_id),
+        ..TestBot::new().await
+    };
+    main.send(Command::Start).await;
+    main.send(Command::NamesOnly).await;
+    assert!(staging.storage.get_subscriber_id(CHAT_ID.to_string()).await.unwrap().is_none());
+    staging.send(Command::Start).await;
+    assert_ne!(main.subscriber_id().await, staging.subscriber_id().await);
+    assert_eq!(main.storage.get_subscribers_chat_ids().await.unwrap(), vec![CHAT_ID.to_string()]);
+    for (bot, descriptions_format) in [(&main, process::NAMES_ONLY), (&staging, process::FULL_DESCRIPTIONS)] {
+        let state = bot.storage.get_chat_state(CHAT_ID.to_string()).await.unwrap().unwrap();
+        assert_eq!(state.descriptions_format, descriptions_format);
+    }
+
+    let language = Language::new("en");
+    let card_id = main.storage.search_cards("", &language, 1).await.unwrap()[0].id;
+    main.storage.set_file_id(card_id, &language, false, "main".to_string(), "hash".to_string()).await.unwrap();
+    assert_eq!(main.storage.get_file_ids().await.unwrap().len(), 1);
+    assert!(staging.storage.get_file_ids().await.unwrap().is_empty());
+    assert!(staging.storage.search_cards("", &language, 1).await.unwrap()[0].telegram_file_id.is_none());
+}
+
+#[test]
+fn heavier_cards_are_chosen_more_often() {
+    let card = |id: i32, weight: i32| process::Card {
+        id,
+        filename: format!("{}.png", id),
+        name: id.to_string(),
+        description: String::new(),
+        telegram_file_id: None,
+        reversed_name: None,
+        reversed_description: None,
+        reversed_telegram_file_id: None,
+        weight,
+    };
+    assert!(process::choose_cards(vec![card(1, 1)], 2).is_none());
+    let mut chosen = process::choose_cards(vec![card(1, 1), card(2, 5), card(3, 1)], 3).unwrap();
+    chosen.sort_by_key(|card| card.id);
+    assert_eq!(chosen.iter().map(|card| card.id).collect::<Vec<i32>>(), vec![1, 2, 3]);
+
+    let heavy = (0..200)
+        .filter(|_| process::choose_cards(vec![card(1, 1), card(2, 1000)], 1).unwrap()[0].id == 2)
+        .count();
+    assert!(heavy > 180);
+}
+
+#[tokio::test]
+async fn deck_with_replacement_keeps_drawn_cards() {
+    let bot = TestBot::with_storage(Box::new(SqliteStorage::new("sqlite::memory:", 1).await.unwrap())).await;
+    let exported = ExportedDeck::new(&bot, "weighted", "name: Weighted\nwith_replacement: true", |cards| {
+        assert!(cards.iter().all(|card| card.weight == deck_files::DEFAULT_WEIGHT));
+        cards[0].weight = 3;
+    })
+    .await;
+    let report = exported.import(&bot, false).await.unwrap();
+    assert!(report.with_replacement);
+    let deck = bot.storage.get_decks().await.unwrap().pop().unwrap();
+    assert!(deck.with_replacement);
+    assert_eq!(bot.storage.get_deck_cards(deck.id).await.unwrap()[0].weight, 3);
+
+    bot.send(Command::Start).await;
+    let subscriber_id = bot.subscriber_id().await;
+    bot.storage.set_deck(deck.id, subscriber_id).await.unwrap();
+    let language = Language::new("en");
+    for _ in 0..CARDS_COUNT + 2 {
+        let drawn = bot.storage.draw_cards(CARDS_COUNT, None, &language, subscriber_id).await.unwrap();
+        assert_eq!(drawn.unwrap().1.len(), CARDS_COUNT);
+    }
+    assert!(bot.storage.draw_cards(CARDS_COUNT + 1, None, &language, subscriber_id).await.unwrap().is_none());
+
+    let data = "file_name,weight,name_en,description_en\nshiva.png,0,Shiva,\n";
+    std::fs::write(exported.dir.join("data.csv"), data).unwrap();
+    assert!(deck_import::check_deck_folder(&exported.dir).is_err());
+}
+
+#[test]
+fn card_texts_are_escaped_and_measured() {
+    assert_eq!(markup::bold("Tom & <Jerry>"), "<b>Tom &amp; &lt;Jerry&gt;</b>");
+    assert_eq!(markup::visible_length(&markup::bold("Tom & <Jerry>")), "Tom & <Jerry>".len());
+    assert_eq!(markup::visible_length("😀"), 2);
+    let long = markup::escape_html(&"&".repeat(markup::CAPTION_LIMIT));
+    assert!(markup::fits_caption(&long));
+    assert!(!markup::fits_caption(&format!("{}a", long)));
+}
+
+#[test]
+fn markdown_is_rendered_to_safe_html() {
+    let render = |text| markup::render(text, markup::Format::Markdown);
+    assert_eq!(
+        render("**Bold** _and_ [a <link>](https://example.com/?a=1&b=2)\n2 \\* 2 < 5").unwrap(),
+        "<b>Bold</b> <i>and</i> <a href=\"https://example.com/?a=1&amp;b=2\">a &lt;link&gt;</a>\n2 * 2 &lt; 5"
+    );
+    assert_eq!(render("_snake_case_ and snake_case").unwrap(), "<i>snake_case</i> and snake_case");
+    assert_eq!(markup::render("**not bold**", markup::Format::Plain).unwrap(), "**not bold**");
+    for malformed in ["**unclosed", "_unclosed", "**_overlapping**_", "[no link]", "[bad](javascript:alert(1))"] {
+        assert!(render(malformed).is_err(), "{}", malformed);
+    }
+}
+
+#[tokio::test]
+async fn markdown_descriptions_are_checked_on_import() {
+    let bot = TestBot::with_storage(Box::new(SqliteStorage::new("sqlite::memory:", 1).await.unwrap())).await;
+    let mut exported = ExportedDeck::new(&bot, "marked", "name: Marked\nmarkup: markdown", |cards| {
+        for card in cards {
+            card.translations[0].description = "**Bold** & _italic_".to_string();
+        }
+    })
+    .await;
+    let report = exported.import(&bot, false).await.unwrap();
+    assert_eq!(report.markup, markup::Format::Markdown);
+    let deck = bot.storage.get_decks().await.unwrap().pop().unwrap();
+    assert_eq!(deck.markup, markup::Format::Markdown);
+    bot.send(Command::Start).await;
+    let subscriber_id = bot.subscriber_id().await;
+    bot.storage.set_deck(deck.id, subscriber_id).await.unwrap();
+    let language = Language::new("en");
+    let (deck, drawn) = bot.storage.draw_cards(1, None, &language, subscriber_id).await.unwrap().unwrap();
+    let card_data = process::card_data(&deck, &drawn[0].0, false, &language, process::FULL_DESCRIPTIONS).unwrap();
+    assert!(card_data.message_text.ends_with("</b>\n<b>Bold</b> &amp; <i>italic</i>"));
+
+    exported.cards[0].translations[0].description = "**unclosed".to_string();
+    deck_files::write_cards(&exported.dir, &exported.cards).unwrap();
+    let error = exported.import(&bot, false).await.err().unwrap();
+    assert!(error.to_string().contains("malformed description"));
+}
+
+#[tokio::test]
+async fn translations_fill_placeholders_and_plural_forms() {
+    let bot = TestBot::new().await;
+    let shuffled = |language, remaining: i64| {
+        let args = [("remaining", remaining.to_string()), ("deck", "<Main>".to_string())];
+        bot.text_with("cards_shuffled_back", language, &args)
+    };
+    assert!(shuffled("en", 1).ends_with(" 1 card in the deck &lt;Main&gt;"));
+    assert!(shuffled("en", 11).ends_with(" 11 cards in the deck &lt;Main&gt;"));
+    for (remaining, form) in [(1, "карта"), (21, "карта"), (3, "карты"), (12, "карт"), (25, "карт")] {
+        assert!(shuffled("ru", remaining).ends_with(&format!(" {} {}", remaining, form)));
+    }
+    assert!(bot.translation.get_with("drawn_by", &Language::new("en"), &[]).is_err());
+}
+
+#[test]
+fn translations_must_match_the_default_language() {
+    let root = std::env::temp_dir().join(format!("deckbot_translations_{}", std::process::id()));
+    let write = |code: &str, text: &str| {
+        std::fs::create_dir_all(root.join(code)).unwrap();
+        std::fs::write(root.join(code).join("translation.yml"), text).unwrap();
+    };
+    write("en", "left: \"{remaining} {remaining:card|cards} left\"\nhello: \"Hello\"");
+    write("ru", "left: \"Осталось {remaining} {remaining:карта|карты|карт}\"\nhello: \"Привет\"");
+    let path = root.to_string_lossy().to_string();
+    assert!(translations::translation(&path, "en").unwrap().problems().is_empty());
+    write("ru", "left: \"Осталось {count} {count:карта|карты}\"\nbye: \"Пока\"");
+    let translation = translations::translation(&path, "en").unwrap();
+    assert_eq!(
+        translation.problems(),
+        [
+            "ru: bye is not in en",
+            "ru: left has other placeholders than in en",
+            "ru: left needs 3 plural forms of count",
+            "ru: no hello",
+        ]
+    );
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn missing_translations_only_warn_on_start() {
+    let root = std::env::temp_dir().join(format!("deckbot_startup_check_{}", std::process::id()));
+    for code in ["en", "ru"] {
+        std::fs::create_dir_all(root.join(code)).unwrap();
+        let text = std::fs::read_to_string(std::path::Path::new(PICTURES_PATH).join(code).join("translation.yml"))
+            .unwrap();
+        let text: String = text
+            .lines()
+            .filter(|line| code == "en" || !line.starts_with("deck_selected:"))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        std::fs::write(root.join(code).join("translation.yml"), text).unwrap();
+    }
+    let path = root.to_string_lossy().to_string();
+    let translation = translations::translation(&path, "en").unwrap();
+    let report = check::check(&translation, &path, &MemoryStorage::new()).await.unwrap();
+    assert!(report.missing_keys.is_empty());
+    assert_eq!(report.translation_problems, vec!["ru: no deck_selected"]);
+    assert!(report.apply(StartupCheck::Warn).is_ok());
+    assert!(report.apply(StartupCheck::Refuse).is_err());
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn check_reports_missing_keys_and_images() {
+    let bot = TestBot::new().await;
+    assert!(check::check(&bot.translation, PICTURES_PATH, &*bot.storage).await.unwrap().is_ok());
+
+    let root = std::env::temp_dir().join(format!("deckbot_check_{}", std::process::id()));
+    std::fs::create_dir_all(root.join("en")).unwrap();
+    std::fs::write(root.join("en/translation.yml"), "start: \"Hello\"").unwrap();
+    let data = "file_name,name_en,description_en\nshiva.png,Shiva,\nmissing.png,Missing,\ntext.png,Text,\n";
+    std::fs::write(root.join("data.csv"), data).unwrap();
+    std::fs::copy(std::path::Path::new(PICTURES_PATH).join("en/shiva.png"), root.join("en/shiva.png")).unwrap();
+    std::fs::write(root.join("en/text.png"), "not an image").unwrap();
+    let path = root.to_string_lossy().to_string();
+    let storage = MemoryStorage::new();
+    storage.check_cards_table(&path).await.unwrap();
+    let translation = translations::translation(&path, "en").unwrap();
+
+    let report = check::check(&translation, &path, &storage).await.unwrap();
+    assert!(!report.is_ok());
+    assert!(report.missing_keys.contains(&"en: help".to_string()));
+    assert!(!report.missing_keys.contains(&"en: start".to_string()));
+    assert_eq!(report.missing_images, vec![file_ids::image_path(".", "en", "missing.png")]);
+    assert_eq!(report.invalid_images.len(), 1);
+    assert!(report.invalid_images[0].starts_with(&file_ids::image_path(".", "en", "text.png")));
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn errors_are_sorted_by_kind() {
+    let blocked = Error::from(anyhow::Error::from(RequestError::Api(ApiError::BotBlocked)));
+    assert!(matches!(blocked, Error::Blocked(_)));
+    assert_eq!(blocked.message_key(), None);
+    let old_message = Error::from(anyhow::Error::from(RequestError::Api(ApiError::MessageToEditNotFound)));
+    assert_eq!(old_message.message_key(), Some("message_not_found"));
+    let database = Error::from(anyhow::Error::from(sqlx::Error::RowNotFound));
+    assert_eq!(database.message_key(), Some("database_error"));
+    assert!(matches!(Error::from(anyhow::anyhow!("something")), Error::Other(_)));
+
+    let bot = TestBot::new().await;
+    let missing = bot.translation.get("no_such_key", &Language::new("en")).err().unwrap();
+    assert_eq!(Error::from(missing).message_key(), Some("data_error"));
+    bot.send(Command::Start).await;
+    let subscriber_id = bot.subscriber_id().await;
+    let drawn = bot.storage.random_card_file_name(&Language::new("en"), 99, subscriber_id).await;
+    assert!(matches!(Error::from(drawn.err().unwrap()), Error::Data(_)));
+}
@@ -1,63 +1,276 @@
 use anyhow::{anyhow, Result};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
+use crate::error::Error;
+use crate::markup;
 use crate::process;
 
 pub struct Translation {
-    en: HashMap<String, String>,
-    ru: HashMap<String, String>,
+    //language code -> (key -> text), message texts are html and button texts are plain
+    languages: BTreeMap<String, HashMap<String, String>>,
+    default_language: process::Language,
+    //the languages which don't match the default one, they are reported by the check
+    problems: Vec<String>,
 }
 
 impl Translation {
     pub fn get(
         &self,
         key: &str,
-        language: process::Language,
+        language: &process::Language,
     ) -> Result<String> {
-        let selected_translation = match language {
-            process::Language::En => &self.en,
-            process::Language::Ru => &self.ru,
-        };
+        let selected_translation = self
+            .languages
+            .get(language.code())
+            .ok_or_else(|| Error::Data(format!("Missing language {}!", language)))?;
         Ok(selected_translation
             .get(key)
-            .ok_or(anyhow!("Missing translation!"))?
+            .ok_or_else(|| Error::Data(format!("Missing translation {} in {}!", key, language)))?
             .to_string())
     }
+
+    //the text with its {placeholders} replaced by the arguments with the same names,
+    //{name:form|forms} is the plural form for the number in the argument name
+    pub fn get_with(
+        &self,
+        key: &str,
+        language: &process::Language,
+        args: &[(&str, String)],
+    ) -> Result<String> {
+        let template = self.get(key, language)?;
+        let mut text = String::new();
+        let mut rest = template.as_str();
+        while let Some((before, after)) = rest.split_once('{') {
+            let (placeholder, after) = after
+                .split_once('}')
+                .ok_or_else(|| Error::Data(format!("Unclosed placeholder in {}!", key)))?;
+            let (name, forms) = match placeholder.split_once(':') {
+                Some((name, forms)) => (name, Some(forms)),
+                None => (placeholder, None),
+            };
+            let value = args
+                .iter()
+                .find(|(arg, _)| *arg == name)
+                .map(|(_, value)| value)
+                .ok_or_else(|| Error::Data(format!("No argument {} for {}!", name, key)))?;
+            text.push_str(before);
+            match forms {
+                Some(forms) => {
+                    let number = value
+                        .parse()
+                        .map_err(|_| Error::Data(format!("{} is not a number for {}!", value, key)))?;
+                    let form = forms
+                        .split('|')
+                        .nth(plural_index(language.code(), number))
+                        .ok_or_else(|| Error::Data(format!("Missing plural form of {} in {}!", name, key)))?;
+                    text.push_str(form);
+                }
+                //texts are html
+                None => text.push_str(&markup::escape_html(value)),
+            }
+            rest = after;
+        }
+        text.push_str(rest);
+        Ok(text)
+    }
+
+    pub fn default_language(&self) -> &process::Language {
+        &self.default_language
+    }
+
+    pub fn languages(&self) -> impl Iterator<Item = process::Language> + '_ {
+        self.languages.keys().map(|code| process::Language::new(code))
+    }
+
+    pub fn problems(&self) -> &[String] {
+        &self.problems
+    }
+
+    pub fn has_language(&self, code: &str) -> bool {
+        self.languages.contains_key(code)
+    }
+
+    //finds the key of a button by its text in any language
+    pub fn button_key(&self, text: &str) -> Option<&str> {
+        self.languages.values().find_map(|translation| {
+            translation
+                .iter()
+                .find(|(key, value)| key.starts_with(BUTTON_PREFIX) && value.as_str() == text)
+                .map(|(key, _)| key.as_str())
+        })
+    }
+
+    //finds the language by its name written in this language
+    pub fn language_by_name(&self, name: &str) -> Option<process::Language> {
+        self.languages
+            .iter()
+            .find(|(_, translation)| translation.get(LANGUAGE_NAME).map(|n| n.as_str()) == Some(name))
+            .map(|(code, _)| process::Language::new(code))
+    }
 }
 
-pub fn translation() -> Result<Translation> {
-    let file_string_ru = std::fs::read_to_string("pictures/ru/translation.yml")?;
-    let file_string_en = std::fs::read_to_string("pictures/en/translation.yml")?;
-    let result_ru: HashMap<String, String> = serde_yaml::from_str(&file_string_ru)?;
-    let result_en: HashMap<String, String> = serde_yaml::from_str(&file_string_en)?;
-    Ok(Translation{
-        en: result_en,
-        ru: result_ru,
+//every folder in pictures_path with a translation.yml file is a language,
+//the folder name is the language code
+pub fn translation(pictures_path: &str, default_language: &str) -> Result<Translation> {
+    let mut languages = BTreeMap::new();
+    for entry in std::fs::read_dir(pictures_path)? {
+        let path = entry?.path();
+        let file_path = path.join("translation.yml");
+        if !file_path.is_file() {
+            continue;
+        }
+        let code = path
+            .file_name()
+            .ok_or(anyhow!("Wrong language folder!"))?
+            .to_string_lossy()
+            .to_string();
+        let file_string = std::fs::read_to_string(file_path)?;
+        let result: HashMap<String, String> = serde_yaml::from_str(&file_string)?;
+        languages.insert(code.clone(), rendered(result).map_err(|e| anyhow!("{}/translation.yml: {}", code, e))?);
+    }
+    if !languages.contains_key(default_language) {
+        return Err(anyhow!("No translation for the default language {}!", default_language));
+    }
+    let problems = check_languages(&languages, default_language);
+    Ok(Translation {
+        languages,
+        default_language: process::Language::new(default_language),
+        problems,
     })
 }
 
+//every language has the keys of the default language with the same placeholders
+//and as many plural forms as it has plural categories, returns all the problems found
+fn check_languages(languages: &BTreeMap<String, HashMap<String, String>>, default_language: &str) -> Vec<String> {
+    let mut problems = vec![];
+    let mut expected = BTreeMap::new();
+    for (key, text) in &languages[default_language] {
+        expected.insert(key, placeholders(default_language, key, text, &mut problems));
+    }
+    for (code, translation) in languages.iter().filter(|(code, _)| code.as_str() != default_language) {
+        for (key, names) in &expected {
+            match translation.get(key.as_str()) {
+                None => problems.push(format!("{}: no {}", code, key)),
+                Some(text) => {
+                    if &placeholders(code, key, text, &mut problems) != names {
+                        problems.push(format!("{}: {} has other placeholders than in {}", code, key, default_language));
+                    }
+                }
+            }
+        }
+        for key in translation.keys().filter(|key| !expected.contains_key(key)) {
+            problems.push(format!("{}: {} is not in {}", code, key, default_language));
+        }
+    }
+    problems.sort();
+    problems
+}
+
+//the names of the placeholders in the text, with a problem if it has the wrong number of plural forms
+fn placeholders(code: &str, key: &str, text: &str, problems: &mut Vec<String>) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    for placeholder in text.split('{').skip(1) {
+        let placeholder = placeholder.split('}').next().unwrap_or_default();
+        let (name, forms) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+        if !forms.is_empty() && forms.split('|').count() != plural_forms(code) {
+            problems.push(format!("{}: {} needs {} plural forms of {}", code, key, plural_forms(code), name));
+        }
+        names.insert(name.to_string());
+    }
+    names
+}
+
+//how many plural forms the language has, in the order of plural_index
+fn plural_forms(code: &str) -> usize {
+    match code {
+        "ru" | "uk" | "be" => 3,
+        "ja" | "zh" | "ko" | "vi" | "th" => 1,
+        _ => 2,
+    }
+}
+
+//the plural form for the number by the rules of the language
+fn plural_index(code: &str, number: i64) -> usize {
+    let number = number.abs();
+    match plural_forms(code) {
+        //one, few, many
+        3 => match (number % 10, number % 100) {
+            (1, n) if n != 11 => 0,
+            (2..=4, n) if !(12..=14).contains(&n) => 1,
+            _ => 2,
+        },
+        1 => 0,
+        //one, other
+        _ => usize::from(number != 1),
+    }
+}
 
+//message texts are turned into html by the markup format of the file,
+//buttons can't have markup so their texts are kept as they are
+fn rendered(mut translation: HashMap<String, String>) -> Result<HashMap<String, String>> {
+    let format = match translation.remove(MARKUP) {
+        Some(format) => format.parse()?,
+        None => markup::Format::Plain,
+    };
+    translation
+        .into_iter()
+        .map(|(key, text)| {
+            if key.starts_with(BUTTON_PREFIX) || PLAIN_KEYS.contains(&key.as_str()) {
+                return Ok((key, text));
+            }
+            let html = markup::render(&text, format).map_err(|e| anyhow!("{}: {}", key, e))?;
+            Ok((key, html))
+        })
+        .collect()
+}
 
-//consts for buttons' texts
-pub const ABOUT_EN: &str = "About";
-pub const ABOUT_RU: &str = "О проекте";
-pub const MAIN_MENU_EN: &str = "Main menu";
-pub const MAIN_MENU_RU: &str = "Главное меню";
-pub const CARD_EN: &str = "Draw a card";
-pub const CARD_RU: &str = "Вытянуть карту";
-pub const SHUFFLE_EN: &str = "Shuffle drawn cards back";
-pub const SHUFFLE_RU: &str = "Замешать вытянутые карты в колоду";
-pub const SETTINGS_EN: &str = "Settings";
-pub const SETTINGS_RU: &str = "Настройки";
-pub const LANGUAGE_EN: &str = "Language";
-pub const LANGUAGE_RU: &str = "Язык";
-pub const DESCRIPTIONS_EN: &str = "Descriptions";
-pub const DESCRIPTIONS_RU: &str = "Описания";
-pub const EN_EN: &str = "English";
-pub const RU_RU: &str = "Русский";
-pub const FULL_DESCRIPTIONS_EN: &str = "Full descriptions";
-pub const FULL_DESCRIPTIONS_RU: &str = "Полные описания";
-pub const NAMES_ONLY_EN: &str = "Names only";
-pub const NAMES_ONLY_RU: &str = "Только имена";
-pub const NO_DESCRIPTIONS_EN: &str = "No descriptions";
-pub const NO_DESCRIPTIONS_RU: &str = "Без описаний";
+//keys for buttons' texts
+pub const BUTTON_PREFIX: &str = "button_";
+pub const ABOUT: &str = "button_about";
+pub const MAIN_MENU: &str = "button_main_menu";
+pub const CARD: &str = "button_card";
+pub const SPREADS: &str = "button_spreads";
+pub const SHUFFLE: &str = "button_shuffle";
+pub const SETTINGS: &str = "button_settings";
+pub const LANGUAGE: &str = "button_language";
+pub const DESCRIPTIONS: &str = "button_descriptions";
+pub const DECK: &str = "button_deck";
+pub const FULL_DESCRIPTIONS: &str = "button_full_descriptions";
+pub const NAMES_ONLY: &str = "button_names_only";
+pub const NO_DESCRIPTIONS: &str = "button_no_descriptions";
+pub const REVERSED: &str = "button_reversed";
+pub const REVERSED_ON: &str = "button_reversed_on";
+pub const REVERSED_OFF: &str = "button_reversed_off";
+pub const HISTORY: &str = "button_history";
+pub const PREVIOUS_PAGE: &str = "button_previous_page";
+pub const NEXT_PAGE: &str = "button_next_page";
+pub const DAILY_CARD: &str = "button_daily_card";
+pub const DAILY_CARD_OFF: &str = "button_daily_card_off";
+pub const GROUP_DECKS: &str = "button_group_decks";
+pub const SHARED_DECK: &str = "button_shared_deck";
+pub const MEMBER_DECKS: &str = "button_member_decks";
+//the name of the language written in this language, used for its button
+pub const LANGUAGE_NAME: &str = "language_name";
+//other texts used in buttons
+const PLAIN_KEYS: [&str; 2] = [LANGUAGE_NAME, "reversed_mark"];
+//the keys of the message texts the bot sends
+pub const MESSAGE_KEYS: [&str; 47] = [
+    "start", "help", "description", "command_not_found", "choose_your_action", "no_cards_left",
+    "cards_shuffled_back", "settings", "language_settings", "descriptions_settings", "language_set",
+    "full_descriptions_will_be_shown", "names_only_will_be_shown", "no_descriptions", "unknown_error",
+    "text_expected", "choose_spread", "not_enough_cards_for_spread", "spread_not_found", "deck_settings",
+    "current_deck", "deck_selected", "reversed_settings", "reversed_cards_enabled", "reversed_cards_disabled",
+    "history", "history_empty", "draw_not_found", "reversed_mark", "daily_card", "daily_card_settings",
+    "daily_card_on", "daily_card_off", "daily_card_wrong_format", "group_settings_admins_only",
+    "group_decks_settings", "shared_deck_set", "member_decks_set", "drawn_by", "deck_empty",
+    "card_not_found", "found_cards", "find_usage", "database_error", "rate_limited", "message_not_found",
+    "data_error",
+];
+//the keys of the button texts
+pub const BUTTON_KEYS: [&str; 24] = [
+    ABOUT, MAIN_MENU, CARD, SPREADS, SHUFFLE, SETTINGS, LANGUAGE, DESCRIPTIONS, DECK, FULL_DESCRIPTIONS,
+    NAMES_ONLY, NO_DESCRIPTIONS, REVERSED, REVERSED_ON, REVERSED_OFF, HISTORY, PREVIOUS_PAGE, NEXT_PAGE,
+    DAILY_CARD, DAILY_CARD_OFF, GROUP_DECKS, SHARED_DECK, MEMBER_DECKS, LANGUAGE_NAME,
+];
+//the markup format of the texts in translation.yml, plain if not set
+pub const MARKUP: &str = "markup";
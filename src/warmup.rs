@@ -0,0 +1,151 @@
+use std::{fmt, path::Path, time::Duration};
+
+use anyhow::Result;
+use teloxide::{prelude::*, types::InputFile, RequestError};
+
+use crate::check::{self, ImageProblem};
+use crate::error::Error;
+use crate::file_ids;
+use crate::process::{self, Language};
+use crate::storage::Storage;
+use crate::telegram_interactions;
+
+//telegram allows about one message per second to the same chat
+const UPLOAD_INTERVAL: Duration = Duration::from_millis(1100);
+
+//an image without a file id, its path is relative to pictures_path
+pub struct PendingImage {
+    pub card_id: i32,
+    pub language: Language,
+    pub reversed: bool,
+    pub image_path: String,
+}
+
+#[derive(Default)]
+pub struct WarmupReport {
+    pub cached: usize,
+    pub uploaded: usize,
+    pub missing: Vec<String>,
+    pub invalid: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+impl fmt::Display for WarmupReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Uploaded {} images, {} already had file ids.", self.uploaded, self.cached)?;
+        writeln!(f, "missing {}: {}", self.missing.len(), self.missing.join(", "))?;
+        writeln!(f, "invalid {}: {}", self.invalid.len(), self.invalid.join(", "))?;
+        write!(f, "failed {}: {}", self.failed.len(), self.failed.join(", "))
+    }
+}
+
+//the images of every card in every language which have no file ids yet, upright ones and reversed ones
+//of the decks some chat draws with reversed cards, missing and invalid images are reported instead
+pub async fn pending_images(
+    languages: &[Language],
+    pictures_path: &str,
+    storage: &dyn Storage,
+) -> Result<(Vec<PendingImage>, WarmupReport)> {
+    let mut report = WarmupReport::default();
+    let mut images = vec![];
+    let reversed_cards_decks = storage.get_reversed_cards_decks().await?;
+    for deck in storage.get_decks().await? {
+        let with_reversed = reversed_cards_decks.contains(&deck.id);
+        for language in languages {
+            for card in storage.search_cards("", language, deck.id).await? {
+                let image_path = file_ids::image_path(&deck.path, language.code(), &card.filename);
+                let mut file_ids = vec![(false, &card.telegram_file_id)];
+                if with_reversed {
+                    file_ids.push((true, &card.reversed_telegram_file_id));
+                }
+                if file_ids.iter().all(|(_, file_id)| file_id.is_some()) {
+                    report.cached += file_ids.len();
+                    continue;
+                }
+                if let Err(e) = check::check_image(&Path::new(pictures_path).join(&image_path)) {
+                    match e {
+                        ImageProblem::Missing => report.missing.push(image_path),
+                        ImageProblem::Invalid(reason) => {
+                            report.invalid.push(format!("{} ({})", image_path, reason))
+                        }
+                    }
+                    continue;
+                }
+                for (reversed, file_id) in file_ids {
+                    if file_id.is_some() {
+                        report.cached += 1;
+                    } else {
+                        images.push(PendingImage {
+                            card_id: card.id,
+                            language: language.clone(),
+                            reversed,
+                            image_path: image_path.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok((images, report))
+}
+
+//uploads the images to the storage chat one by one and saves their file ids,
+//a failed image doesn't stop the others
+pub async fn upload(
+    images: Vec<PendingImage>,
+    storage_chat_id: ChatId,
+    bot: &Bot,
+    pictures_path: &str,
+    storage: &dyn Storage,
+    report: &mut WarmupReport,
+) -> Result<()> {
+    for pending in images {
+        match upload_image(&pending, storage_chat_id, bot, pictures_path, storage).await {
+            Ok(()) => report.uploaded += 1,
+            Err(e) => {
+                let variant = if pending.reversed { ", reversed" } else { "" };
+                report.failed.push(format!("{}{}: {}", pending.image_path, variant, e));
+            }
+        }
+        tokio::time::sleep(UPLOAD_INTERVAL).await;
+    }
+    Ok(())
+}
+
+async fn upload_image(
+    pending: &PendingImage,
+    storage_chat_id: ChatId,
+    bot: &Bot,
+    pictures_path: &str,
+    storage: &dyn Storage,
+) -> Result<()> {
+    let path = Path::new(pictures_path).join(&pending.image_path);
+    let input_file = if pending.reversed {
+        InputFile::memory(process::rotated_image(&path)?).file_name(format!("reversed_{}", pending.card_id))
+    } else {
+        InputFile::file(&path)
+    };
+    let image = process::Image {
+        input_file,
+        text: String::new(),
+        card_id: pending.card_id,
+        language: pending.language.clone(),
+        reversed: pending.reversed,
+        content_hash: Some(file_ids::content_hash(&path)?),
+    };
+    //if telegram asks to slow down, waits and tries once more
+    let message = match bot.send_photo(storage_chat_id, image.input_file.clone()).await {
+        Err(RequestError::RetryAfter(duration)) => {
+            tokio::time::sleep(duration).await;
+            bot.send_photo(storage_chat_id, image.input_file.clone()).await?
+        }
+        result => result?,
+    };
+    telegram_interactions::save_file_id(message, &image, storage).await
+}
+
+pub fn storage_chat_id(storage_chat_id: Option<i64>) -> Result<ChatId> {
+    storage_chat_id
+        .map(ChatId)
+        .ok_or_else(|| Error::Config("storage_chat_id is not set in the config".to_string()).into())
+}